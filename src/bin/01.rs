@@ -45,7 +45,7 @@ fn part_two(numbers: &HashSet<u32>) -> Option<u32> {
     // which add to (TARGET - x), so we can simply reuse part_one.
     // Return None if no triple of numbers is found.
     for x in numbers {
-        if let Some(pair_product) = part_one(&numbers, TARGET - x) {
+        if let Some(pair_product) = part_one(numbers, TARGET - x) {
             return Some(pair_product * x);
         }
     }
@@ -60,7 +60,7 @@ fn test_examples() {
 299
 675
 1456";
-    let numbers = parse_input(&input);
+    let numbers = parse_input(input);
 
     // Check None is returned if there is no solution
     assert_eq!(part_one(&HashSet::new(), 0), None);
@@ -48,7 +48,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn parse_input(input: &str) -> Vec<PasswordRule> {
+fn parse_input(input: &str) -> Vec<PasswordRule<'_>> {
     input.lines().filter_map(PasswordRule::from_str).collect()
 }
 
@@ -85,7 +85,7 @@ fn test_examples() {
             password: "ccccccccc",
         },
     ];
-    assert_eq!(parse_input(&input), expected);
+    assert_eq!(parse_input(input), expected);
     assert_eq!(part_one(&expected), 2);
     assert_eq!(part_two(&expected), 1);
 }
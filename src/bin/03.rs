@@ -1,63 +1,357 @@
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::time::Instant;
 
+// (right, down), as in the puzzle statement
+type Slope = (usize, usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edge {
+    // Carry on from the left hand side of the map, as in the puzzle
+    Wrap,
+    // The route ends when it would leave the right hand side of the map
+    Stop,
+    // Bounce off the left and right hand sides of the map
+    Reflect,
+}
+
+impl FromStr for Edge {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "wrap" => Ok(Edge::Wrap),
+            "stop" => Ok(Edge::Stop),
+            "reflect" => Ok(Edge::Reflect),
+            _ => Err(format!("Unknown edge behaviour {}", input)),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct TreeMap {
     // Outer Vec is columns, inner Vec is rows
     trees: Vec<Vec<bool>>,
+    width: usize,
+    edge: Edge,
 }
 
-impl TreeMap {
-    fn from_str(input: &str) -> Self {
+impl FromStr for TreeMap {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        // Whitespace is ignored, so that maps can be indented. Every row must
+        // be the same (non-zero) width.
         let mut trees = Vec::new();
-        for line in input.lines().filter(|l| !l.is_empty()) {
-            let inner: Vec<bool> = line
+        for (line_num, line) in input.lines().enumerate() {
+            let inner = line
                 .chars()
-                .filter_map(|c| match c {
-                    '.' => Some(false),
-                    '#' => Some(true),
-                    _ => None,
+                .filter(|c| !c.is_whitespace())
+                .map(|c| match c {
+                    '.' => Ok(false),
+                    '#' => Ok(true),
+                    _ => Err(format!("Got bad character {} on line {}", c, line_num + 1)),
                 })
-                .collect();
+                .collect::<Result<Vec<bool>, Self::Err>>()?;
+            if inner.is_empty() {
+                continue;
+            }
+            if let Some(first) = trees.first().map(Vec::len) {
+                if inner.len() != first {
+                    return Err(format!(
+                        "Line {} has width {}, but the map has width {}",
+                        line_num + 1,
+                        inner.len(),
+                        first
+                    ));
+                }
+            }
             trees.push(inner);
         }
-        TreeMap { trees }
+        let width = trees.first().map(Vec::len).ok_or("Map has no rows")?;
+        Ok(TreeMap {
+            trees,
+            width,
+            edge: Edge::Wrap,
+        })
+    }
+}
+
+impl TreeMap {
+    fn with_edge(self, edge: Edge) -> Self {
+        TreeMap { edge, ..self }
     }
 
     fn is_tree(&self, x: usize, y: usize) -> bool {
         // |----> x
         // v y
-        let row = self.trees.get(y).unwrap();
-        *row.get(x % row.len()).unwrap()
+        self.trees[y][x % self.width]
+    }
+
+    fn column(&self, x: usize) -> Option<usize> {
+        // Return the column that a toboggan which has moved x squares to the
+        // right ends up in, if it's still on the map. When wrapping, this is
+        // the column in the infinitely repeated map.
+        match self.edge {
+            Edge::Wrap => Some(x),
+            Edge::Stop if x < self.width => Some(x),
+            Edge::Stop => None,
+            Edge::Reflect => {
+                // Reflecting is periodic, going there and back again
+                let period = 2 * (self.width - 1);
+                if period == 0 {
+                    return Some(0);
+                }
+                let offset = x % period;
+                Some(if offset < self.width {
+                    offset
+                } else {
+                    period - offset
+                })
+            }
+        }
     }
 
     fn count_trees(&self, y_step: usize, x_step: usize) -> usize {
-        let mut num_trees = 0;
-        let mut x = 0;
-        let mut y = 0;
+        self.trace((x_step, y_step)).filter(|&(_, hit)| hit).count()
+    }
 
-        while y < self.trees.len() {
-            if self.is_tree(x, y) {
-                num_trees += 1;
+    fn trace(&self, slope: Slope) -> Route<'_> {
+        Route {
+            tree_map: self,
+            slope,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    fn overlay(&self, slopes: &[Slope]) -> Vec<Vec<Square>> {
+        // Lay the given routes over the map, repeating the map to the right as
+        // many times as is needed to fit the widest route.
+        let mut visited: HashMap<(usize, usize), bool> = HashMap::new();
+        for &slope in slopes {
+            visited.extend(self.trace(slope));
+        }
+        let max_x = visited.keys().map(|&(x, _)| x).max().unwrap_or(0);
+        let repeats = max_x / self.width + 1;
+        self.trees
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                (0..repeats * self.width)
+                    .map(|x| match (visited.get(&(x, y)), row[x % self.width]) {
+                        (Some(_), true) => Square::Hit,
+                        (Some(_), false) => Square::Passed,
+                        (None, true) => Square::Tree,
+                        (None, false) => Square::Open,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn render(&self, slopes: &[Slope]) -> String {
+        // Draw the routes in the style of the puzzle statement - O for an open
+        // square on a route and X for a tree which was hit.
+        let mut output = String::new();
+        for row in self.overlay(slopes) {
+            output.extend(row.iter().map(|square| match square {
+                Square::Open => '.',
+                Square::Tree => '#',
+                Square::Passed => 'O',
+                Square::Hit => 'X',
+            }));
+            output.push('\n');
+        }
+        output
+    }
+
+    fn render_ppm(&self, slopes: &[Slope], scale: usize) -> String {
+        // Draw the routes as a plain-text PPM image, with each square taking up
+        // scale x scale pixels.
+        let squares = self.overlay(slopes);
+        let width = squares.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut output = format!("P3\n{} {}\n255\n", width * scale, squares.len() * scale);
+        for row in &squares {
+            let mut line = String::new();
+            for x in 0..width {
+                let colour = match row.get(x) {
+                    Some(Square::Open) => "255 255 255",
+                    Some(Square::Tree) => "34 139 34",
+                    Some(Square::Passed) => "30 144 255",
+                    Some(Square::Hit) => "220 20 60",
+                    None => "0 0 0",
+                };
+                for _ in 0..scale {
+                    line.push_str(colour);
+                    line.push('\n');
+                }
+            }
+            for _ in 0..scale {
+                output.push_str(&line);
+            }
+        }
+        output
+    }
+
+    fn product_of_trees(&self, slopes: &[Slope]) -> usize {
+        // Multiply together the number of trees hit on each of the slopes
+        slopes
+            .iter()
+            .map(|&(right, down)| self.count_trees(down, right))
+            .product()
+    }
+
+    fn rank_slopes(&self, max_right: usize, max_down: usize) -> Vec<(Slope, usize)> {
+        // Count the trees on every rational slope with 0 <= right <= max_right
+        // and 1 <= down <= max_down, sorted from fewest to most trees (ties
+        // broken by the slope itself).
+        // Only slopes in lowest terms are considered - e.g. (2, 2) visits a
+        // subset of the squares visited by (1, 1), so isn't a distinct slope.
+        // When wrapping, moving right by more than the width of the map is the
+        // same as moving right by that amount modulo the width, so each
+        // horizontal step is only ever walked once.
+        let mut cache: HashMap<Slope, usize> = HashMap::new();
+        let mut ranking = Vec::new();
+        for down in 1..=max_down {
+            for right in 0..=max_right {
+                if gcd(right, down) != 1 {
+                    continue;
+                }
+                let key = match self.edge {
+                    Edge::Wrap => (right % self.width, down),
+                    _ => (right, down),
+                };
+                let num_trees = *cache
+                    .entry(key)
+                    .or_insert_with(|| self.count_trees(down, key.0));
+                ranking.push(((right, down), num_trees));
             }
-            x += x_step;
-            y += y_step;
         }
-        num_trees
+        ranking.sort_unstable_by_key(|&(slope, num_trees)| (num_trees, slope));
+        ranking
+    }
+}
+
+struct Route<'a> {
+    tree_map: &'a TreeMap,
+    slope: Slope,
+    x: usize,
+    y: usize,
+}
+
+impl Iterator for Route<'_> {
+    // The position visited, and whether or not it is a tree
+    type Item = ((usize, usize), bool);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.y >= self.tree_map.trees.len() {
+            return None;
+        }
+        let x = self.tree_map.column(self.x)?;
+        let position = (x, self.y);
+        let hit = self.tree_map.is_tree(x, self.y);
+        self.x += self.slope.0;
+        self.y += self.slope.1;
+        if self.slope.1 == 0 {
+            // We'd never get to the bottom, so stop after the first square
+            self.y = self.tree_map.trees.len();
+        }
+        Some((position, hit))
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Square {
+    Open,
+    Tree,
+    Passed,
+    Hit,
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    // Uses Euclid's algorithm
+    while b != 0 {
+        let t = b;
+        b = a % b;
+        a = t;
+    }
+    a
+}
+
 fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
     let input = std::fs::read_to_string("input/03")?;
-    let tree_map = parse_input(&input);
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut tree_map = parse_input(&input);
     println!("Part 1: {}", part_one(&tree_map));
     println!("Part 2: {}", part_two(&tree_map));
+    // e.g. `--edge reflect` after any of the commands below to change what
+    // happens at the edges of the map
+    if let Some(i) = args.iter().position(|a| a == "--edge") {
+        let edge: Edge = args.get(i + 1).expect("Expected an edge").parse().unwrap();
+        tree_map = tree_map.with_edge(edge);
+        args.drain(i..(i + 2));
+    }
+    match args.get(1).map(String::as_str) {
+        Some("slopes") => {
+            // e.g. `cargo run --bin 03 -- slopes 100 100` to rank all slopes up
+            // to 100 right and 100 down
+            let bound = |i: usize| args.get(i).and_then(|a| a.parse().ok()).unwrap_or(10);
+            let ranking = tree_map.rank_slopes(bound(2), bound(3));
+            for ((right, down), num_trees) in ranking.iter().take(10) {
+                println!("Right {}, down {}: {} trees", right, down, num_trees);
+            }
+            println!("({} slopes searched)", ranking.len());
+        }
+        Some("render") => {
+            // e.g. `cargo run --bin 03 -- render 3 1 1 2` to draw the routes
+            // for right 3, down 1 and right 1, down 2
+            let slopes = parse_slopes(&args[2..]).map_err(invalid_data)?;
+            print!("{}", tree_map.render(&slopes));
+        }
+        Some("image") => {
+            // e.g. `cargo run --bin 03 -- image routes.ppm 3 1`
+            let path = args.get(2).expect("Expected an output file");
+            let slopes = parse_slopes(&args[3..]).map_err(invalid_data)?;
+            std::fs::write(path, tree_map.render_ppm(&slopes, 4))?;
+        }
+        _ => {}
+    }
     println!("Time: {}µs", now.elapsed().as_micros());
     Ok(())
 }
 
 fn parse_input(input: &str) -> TreeMap {
-    TreeMap::from_str(input)
+    TreeMap::from_str(input).unwrap()
+}
+
+fn parse_slopes(args: &[String]) -> Result<Vec<Slope>, String> {
+    // Pairs of right, down
+    if !args.len().is_multiple_of(2) {
+        return Err(format!(
+            "Expected pairs of right, down but got {} numbers",
+            args.len()
+        ));
+    }
+    let numbers = args
+        .iter()
+        .map(|a| {
+            a.parse()
+                .map_err(|_| format!("Expected a number, got {:?}", a))
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+    numbers
+        .chunks_exact(2)
+        .map(|c| match (c[0], c[1]) {
+            (right, 0) => Err(format!(
+                "Slope right {}, down 0 never reaches the bottom",
+                right
+            )),
+            slope => Ok(slope),
+        })
+        .collect()
+}
+
+fn invalid_data(e: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
 }
 
 fn part_one(tree_map: &TreeMap) -> usize {
@@ -65,14 +359,11 @@ fn part_one(tree_map: &TreeMap) -> usize {
 }
 
 fn part_two(tree_map: &TreeMap) -> usize {
-    [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)]
-        .iter()
-        .map(|t| tree_map.count_trees(t.1, t.0))
-        .product()
+    tree_map.product_of_trees(&[(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)])
 }
-#[test]
-fn test_examples() {
-    let input = "
+
+#[cfg(test)]
+const EXAMPLE: &str = "
     ..##.......
     #...#...#..
     .#....#..#.
@@ -84,7 +375,97 @@ fn test_examples() {
     #.##...#...
     #...##....#
     .#..#...#.#";
-    let tree_map = parse_input(&input);
+
+#[test]
+fn test_examples() {
+    let tree_map = parse_input(EXAMPLE);
     assert_eq!(part_one(&tree_map), 7);
     assert_eq!(part_two(&tree_map), 336);
 }
+
+#[test]
+fn test_rank_slopes() {
+    let tree_map = parse_input(EXAMPLE);
+    let ranking = tree_map.rank_slopes(7, 2);
+    // 8 slopes going down 1, and the 4 odd slopes going down 2
+    assert_eq!(ranking.len(), 12);
+    assert!(!ranking.iter().any(|&(slope, _)| slope == (2, 2)));
+    assert_eq!(ranking[0], ((5, 2), 0));
+    assert!(ranking.windows(2).all(|w| w[0].1 <= w[1].1));
+    for &(slope, num_trees) in &ranking {
+        assert_eq!(tree_map.product_of_trees(&[slope]), num_trees);
+    }
+    // Slopes wider than the map are equivalent to their remainder
+    let wide = tree_map.rank_slopes(14, 1);
+    let count = |slope| wide.iter().find(|&&(s, _)| s == slope).unwrap().1;
+    assert_eq!(count((3, 1)), count((14, 1)));
+}
+
+#[test]
+fn test_render() {
+    let tree_map = parse_input(EXAMPLE);
+    let route: Vec<_> = tree_map.trace((3, 1)).collect();
+    assert_eq!(route.len(), 11);
+    assert_eq!(route[1], ((3, 1), false));
+    assert_eq!(route[2], ((6, 2), true));
+    assert_eq!(route.iter().filter(|&&(_, hit)| hit).count(), 7);
+
+    let expected = "\
+O.##.........##.........##.......
+#..O#...#..#...#...#..#...#...#..
+.#....X..#..#....#..#..#....#..#.
+..#.#...#O#..#.#...#.#..#.#...#.#
+.#...##..#..X...##..#..#...##..#.
+..#.##.......#.X#.......#.##.....
+.#.#.#....#.#.#.#.O..#.#.#.#....#
+.#........#.#........X.#........#
+#.##...#...#.##...#...#.X#...#...
+#...##....##...##....##...#X....#
+.#..#...#.#.#..#...#.#.#..#...X.#
+";
+    assert_eq!(tree_map.render(&[(3, 1)]), expected);
+
+    let image = tree_map.render_ppm(&[(3, 1)], 2);
+    assert!(image.starts_with("P3\n66 22\n255\n"));
+    assert_eq!(image.lines().count(), 3 + 66 * 22);
+
+    // Going nowhere stops straight away rather than looping forever
+    assert_eq!(tree_map.trace((0, 0)).count(), 1);
+    assert_eq!(tree_map.count_trees(0, 3), 0);
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        parse_slopes(&args(&["3", "1", "1", "2"])),
+        Ok(vec![(3, 1), (1, 2)])
+    );
+    assert!(parse_slopes(&args(&["3", "0"])).is_err());
+    assert!(parse_slopes(&args(&["3", "1", "1"])).is_err());
+    assert!(parse_slopes(&args(&["3", "x"])).is_err());
+}
+
+#[test]
+fn test_edges() {
+    let tree_map = parse_input(EXAMPLE).with_edge(Edge::Stop);
+    // Right 3 leaves the map after 4 steps
+    assert_eq!(tree_map.trace((3, 1)).count(), 4);
+    assert_eq!(tree_map.count_trees(1, 3), 1);
+    assert_eq!(tree_map.count_trees(1, 0), 3);
+
+    let tree_map = tree_map.with_edge(Edge::Reflect);
+    let columns: Vec<usize> = tree_map.trace((3, 1)).map(|((x, _), _)| x).collect();
+    assert_eq!(columns, vec![0, 3, 6, 9, 8, 5, 2, 1, 4, 7, 10]);
+    assert_eq!(tree_map.count_trees(1, 3), 4);
+    assert_eq!(
+        tree_map.render(&[(3, 1)]).lines().next(),
+        Some("O.##.......")
+    );
+
+    assert_eq!(
+        TreeMap::from_str("..#\n.#\n").unwrap_err(),
+        "Line 2 has width 2, but the map has width 3"
+    );
+    assert_eq!(
+        TreeMap::from_str("..#\n.x.\n").unwrap_err(),
+        "Got bad character x on line 2"
+    );
+    assert_eq!(TreeMap::from_str("\n  \n").unwrap_err(), "Map has no rows");
+}
@@ -1,99 +1,433 @@
+use regex::Regex;
 use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::{mpsc, Mutex};
+use std::thread;
 use std::time::Instant;
 
-fn passport_from_str(input: &str) -> HashMap<&str, &str> {
-    let mut passport = HashMap::new();
-    for kv in input.split(|c| c == ' ' || c == '\n') {
-        let mut kv = kv.split(':');
-        let key = kv.next().unwrap();
-        let value = kv.next().unwrap();
-        passport.insert(key, value);
+#[derive(Debug, Default)]
+struct Passport<'a> {
+    fields: HashMap<&'a str, &'a str>,
+    // Tokens which weren't of the form key:value
+    malformed: Vec<&'a str>,
+}
+
+fn passport_from_str(input: &str) -> Passport<'_> {
+    let mut passport = Passport::default();
+    for kv in input.split_whitespace() {
+        match kv.split_once(':') {
+            Some((key, value)) => {
+                passport.fields.insert(key, value);
+            }
+            None => passport.malformed.push(kv),
+        }
     }
     passport
 }
 
-fn check_valid1(passport: &HashMap<&str, &str>) -> bool {
-    let required_keys = vec!["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
-    required_keys
-        .iter()
-        .map(|&k| passport.contains_key(k))
-        .all(|x| x)
+#[derive(Debug, PartialEq)]
+enum Problem {
+    Missing,
+    Malformed(String),
+    OutOfRange(String),
+    UnknownKey,
 }
 
-fn check_valid2(passport: &HashMap<&str, &str>) -> bool {
-    passport
-        .iter()
-        .map(|(k, v)| match *k {
-            "byr" => {
-                let year = v.parse::<u32>().unwrap();
-                1920 <= year && year <= 2002
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::Malformed(reason) => write!(f, "malformed: {}", reason),
+            Problem::OutOfRange(reason) => write!(f, "out of range: {}", reason),
+            Problem::UnknownKey => write!(f, "unknown key"),
+        }
+    }
+}
+
+enum Constraint {
+    Any,
+    Int(RangeInclusive<u32>),
+    Unit(Vec<(String, RangeInclusive<u32>)>),
+    Regex(Regex),
+    Enum(Vec<String>),
+}
+
+fn check_range(value: &str, range: &RangeInclusive<u32>, unit: &str) -> Result<(), Problem> {
+    let n: u32 = value
+        .parse()
+        .map_err(|_| Problem::Malformed(format!("{} is not an integer", value)))?;
+    if range.contains(&n) {
+        Ok(())
+    } else {
+        Err(Problem::OutOfRange(format!(
+            "{}{} is not in {}..={}{}",
+            n,
+            unit,
+            range.start(),
+            range.end(),
+            unit
+        )))
+    }
+}
+
+impl Constraint {
+    fn check(&self, value: &str) -> Result<(), Problem> {
+        match self {
+            Constraint::Any => Ok(()),
+            Constraint::Int(range) => check_range(value, range, ""),
+            Constraint::Unit(units) => {
+                // Prefer the longest matching unit, so that e.g. km isn't
+                // mistaken for m
+                match units
+                    .iter()
+                    .filter_map(|(unit, range)| {
+                        Some((value.strip_suffix(unit.as_str())?, unit, range))
+                    })
+                    .max_by_key(|(_, unit, _)| unit.len())
+                {
+                    Some((n, unit, range)) => check_range(n, range, unit),
+                    None => {
+                        let units: Vec<&str> = units.iter().map(|(u, _)| u.as_str()).collect();
+                        Err(Problem::Malformed(format!(
+                            "{} doesn't end in one of {}",
+                            value,
+                            units.join(", ")
+                        )))
+                    }
+                }
             }
-            "iyr" => {
-                let year = v.parse::<u32>().unwrap();
-                2010 <= year && year <= 2020
+            Constraint::Regex(regex) if regex.is_match(value) => Ok(()),
+            Constraint::Regex(regex) => Err(Problem::Malformed(format!(
+                "{} doesn't match {}",
+                value,
+                regex.as_str()
+            ))),
+            Constraint::Enum(options) if options.iter().any(|o| o == value) => Ok(()),
+            Constraint::Enum(options) => Err(Problem::OutOfRange(format!(
+                "{} is not one of {}",
+                value,
+                options.join(", ")
+            ))),
+        }
+    }
+}
+
+struct Field {
+    name: String,
+    required: bool,
+    constraint: Constraint,
+}
+
+struct Schema {
+    fields: Vec<Field>,
+}
+
+impl FromStr for Schema {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        // See schema/04 for the format
+        let mut fields = Vec::new();
+        for (line_num, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
             }
-            "eyr" => {
-                let year = v.parse::<u32>().unwrap();
-                2020 <= year && year <= 2030
+            let err = |msg: &str| format!("Line {}: {}: {}", line_num + 1, msg, line);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 3 {
+                return Err(err("expected a field, requirement and constraint"));
             }
-            "hgt" => {
-                if v.ends_with("cm") {
-                    let height: u32 = v.trim_end_matches("cm").parse().unwrap();
-                    150 <= height && height <= 193
-                } else if v.ends_with("in") {
-                    let height: u32 = v.trim_end_matches("in").parse().unwrap();
-                    59 <= height && height <= 76
-                } else {
-                    false
+            let required = match parts[1] {
+                "required" => true,
+                "optional" => false,
+                _ => return Err(err("expected required or optional")),
+            };
+            let range = |min: &str, max: &str| -> Result<RangeInclusive<u32>, String> {
+                match (min.parse(), max.parse()) {
+                    (Ok(min), Ok(max)) => Ok(min..=max),
+                    _ => Err(err("bad range")),
+                }
+            };
+            let args = &parts[3..];
+            let constraint = match (parts[2], args) {
+                ("any", []) => Constraint::Any,
+                ("int", [min, max]) => Constraint::Int(range(min, max)?),
+                ("unit", _) if !args.is_empty() && args.len().is_multiple_of(3) => {
+                    Constraint::Unit(
+                        args.chunks(3)
+                            .map(|u| Ok((u[0].to_string(), range(u[1], u[2])?)))
+                            .collect::<Result<_, String>>()?,
+                    )
+                }
+                ("regex", [pattern]) => Constraint::Regex(
+                    // Patterns must match the whole value
+                    Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| err(&e.to_string()))?,
+                ),
+                ("enum", _) if !args.is_empty() => {
+                    Constraint::Enum(args.iter().map(|a| a.to_string()).collect())
                 }
+                _ => return Err(err("bad constraint")),
+            };
+            fields.push(Field {
+                name: parts[0].to_string(),
+                required,
+                constraint,
+            });
+        }
+        Ok(Schema { fields })
+    }
+}
+
+impl Schema {
+    fn has_required(&self, passport: &Passport) -> bool {
+        self.fields
+            .iter()
+            .filter(|f| f.required)
+            .all(|f| passport.fields.contains_key(f.name.as_str()))
+    }
+
+    fn validate<'a>(&'a self, passport: &Passport<'a>) -> Vec<(&'a str, Problem)> {
+        // Return every problem with the passport, along with the field (or
+        // malformed token) which it is a problem with.
+        // Every required field must be present, and every field must be known
+        // to the schema and meet its constraint.
+        let mut problems = Vec::new();
+        for field in &self.fields {
+            match passport.fields.get(field.name.as_str()) {
+                Some(value) => {
+                    if let Err(problem) = field.constraint.check(value) {
+                        problems.push((field.name.as_str(), problem));
+                    }
+                }
+                None if field.required => problems.push((field.name.as_str(), Problem::Missing)),
+                None => {}
             }
-            "hcl" => {
-                if let Some(stripped) = v.strip_prefix('#') {
-                    v.len() == 7
-                        && stripped
-                            .chars()
-                            .filter(|c| {
-                                c.is_numeric() || ['a', 'b', 'c', 'd', 'e', 'f'].contains(&c)
-                            })
-                            .count()
-                            == 6
-                } else {
-                    false
+        }
+        let mut unknown: Vec<&str> = passport
+            .fields
+            .keys()
+            .filter(|&k| !self.fields.iter().any(|f| f.name == *k))
+            .copied()
+            .collect();
+        unknown.sort_unstable();
+        problems.extend(unknown.into_iter().map(|k| (k, Problem::UnknownKey)));
+        problems.extend(passport.malformed.iter().map(|&token| {
+            (
+                token,
+                Problem::Malformed(format!("{} is not of the form key:value", token)),
+            )
+        }));
+        problems
+    }
+
+    fn is_valid(&self, passport: &Passport) -> bool {
+        self.validate(passport).is_empty()
+    }
+}
+
+struct Records<R> {
+    reader: R,
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    // The text of one passport, which may span several lines
+    type Item = io::Result<String>;
+    fn next(&mut self) -> Option<Self::Item> {
+        // Read lines up to the next blank line (or the end of the input),
+        // skipping over any leading blank lines.
+        let mut record = String::new();
+        loop {
+            let len_before = record.len();
+            match self.reader.read_line(&mut record) {
+                Err(e) => return Some(Err(e)),
+                Ok(0) => break,
+                Ok(_) if is_blank(&record[len_before..]) => {
+                    record.truncate(len_before);
+                    if !record.is_empty() {
+                        break;
+                    }
                 }
+                Ok(_) => {}
+            }
+        }
+        if record.is_empty() {
+            None
+        } else {
+            Some(Ok(record))
+        }
+    }
+}
+
+fn records<R: BufRead>(reader: R) -> Records<R> {
+    Records { reader }
+}
+
+// Passports are separated by lines with nothing but whitespace on them
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+fn split_records(input: &str) -> Vec<&str> {
+    // The same records as `records` gives, but borrowed from the input
+    let mut split = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        if is_blank(line) {
+            if let Some(start) = start.take() {
+                split.push(&input[start..offset]);
             }
-            "ecl" => ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"].contains(v),
-            "pid" => v.len() == 9 && v.chars().filter(|c| c.is_numeric()).count() == 9,
-            "cid" => true,
-            _ => false,
+        } else if start.is_none() {
+            start = Some(offset);
+        }
+        offset += line.len();
+    }
+    if let Some(start) = start {
+        split.push(&input[start..]);
+    }
+    split
+}
+
+fn count_valid<R: BufRead>(
+    reader: R,
+    schema: &Schema,
+    threads: usize,
+) -> io::Result<(usize, usize)> {
+    // Stream passports from the reader, returning the number which have all
+    // the required fields and the number which are fully valid.
+    // Only a bounded number of passports are held in memory at once. With more
+    // than one thread, batches of passports are handed out to worker threads
+    // to be validated.
+    const BATCH_SIZE: usize = 1024;
+    let count = |batch: &[String]| {
+        batch.iter().fold((0, 0), |(valid1, valid2), record| {
+            let passport = passport_from_str(record);
+            (
+                valid1 + schema.has_required(&passport) as usize,
+                valid2 + schema.is_valid(&passport) as usize,
+            )
         })
-        .all(|x| x)
-        && check_valid1(&passport)
+    };
+    let mut records = records(reader);
+    let mut next_batch =
+        || -> io::Result<Vec<String>> { records.by_ref().take(BATCH_SIZE).collect() };
+
+    if threads <= 1 {
+        let mut totals = (0, 0);
+        loop {
+            let batch = next_batch()?;
+            if batch.is_empty() {
+                return Ok(totals);
+            }
+            let (valid1, valid2) = count(&batch);
+            totals = (totals.0 + valid1, totals.1 + valid2);
+        }
+    }
+
+    let (sender, receiver) = mpsc::sync_channel::<Vec<String>>(threads * 2);
+    let receiver = Mutex::new(receiver);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut totals = (0, 0);
+                    // Hold the lock only for as long as it takes to receive
+                    while let Ok(batch) = { receiver.lock().unwrap().recv() } {
+                        let (valid1, valid2) = count(&batch);
+                        totals = (totals.0 + valid1, totals.1 + valid2);
+                    }
+                    totals
+                })
+            })
+            .collect();
+        let sent = loop {
+            match next_batch() {
+                Ok(batch) if batch.is_empty() => break Ok(()),
+                Ok(batch) => sender.send(batch).unwrap(),
+                Err(e) => break Err(e),
+            }
+        };
+        // Hang up so that the workers finish
+        drop(sender);
+        let totals = workers
+            .into_iter()
+            .map(|w| w.join().unwrap())
+            .fold((0, 0), |acc, t| (acc.0 + t.0, acc.1 + t.1));
+        sent.map(|_| totals)
+    })
 }
 
 fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
+    let args: Vec<String> = std::env::args().collect();
+    let schema = parse_schema(&std::fs::read_to_string("schema/04")?);
+    if args.get(1).map(String::as_str) == Some("stream") {
+        // `cargo run --bin 04 -- stream <file> <threads>` to validate a file of
+        // any size without reading it all into memory
+        let path = args.get(2).map_or("input/04", String::as_str);
+        let threads = args.get(3).map_or(1, |t| t.parse().unwrap());
+        let (valid1, valid2) = count_valid(BufReader::new(File::open(path)?), &schema, threads)?;
+        println!("Part 1: {}", valid1);
+        println!("Part 2: {}", valid2);
+        println!("Time: {}µs", now.elapsed().as_micros());
+        return Ok(());
+    }
     let input = std::fs::read_to_string("input/04")?;
-    let passports: Vec<HashMap<_, _>> = parse_input(&input);
-    println!("Part 1: {}", part_one(&passports));
-    println!("Part 2: {}", part_two(&passports));
+    let passports = parse_input(&input);
+    println!("Part 1: {}", part_one(&passports, &schema));
+    println!("Part 2: {}", part_two(&passports, &schema));
+    if args.get(1).map(String::as_str) == Some("report") {
+        // `cargo run --bin 04 -- report` to see why each passport is invalid
+        print!("{}", report(&passports, &schema));
+    }
     println!("Time: {}µs", now.elapsed().as_micros());
     Ok(())
 }
 
-fn parse_input(input: &str) -> Vec<HashMap<&str, &str>> {
-    input.split("\n\n").map(|p| passport_from_str(&p)).collect()
+fn parse_input(input: &str) -> Vec<Passport<'_>> {
+    split_records(input)
+        .into_iter()
+        .map(passport_from_str)
+        .collect()
+}
+
+fn parse_schema(input: &str) -> Schema {
+    Schema::from_str(input).unwrap()
+}
+
+fn report(passports: &[Passport], schema: &Schema) -> String {
+    // One line for each passport, listing everything wrong with it
+    let mut output = String::new();
+    for (i, passport) in passports.iter().enumerate() {
+        let problems: Vec<String> = schema
+            .validate(passport)
+            .iter()
+            .map(|(field, problem)| format!("{} {}", field, problem))
+            .collect();
+        if problems.is_empty() {
+            output.push_str(&format!("Passport {}: valid\n", i + 1));
+        } else {
+            output.push_str(&format!("Passport {}: {}\n", i + 1, problems.join("; ")));
+        }
+    }
+    output
 }
 
-fn part_one(passports: &[HashMap<&str, &str>]) -> usize {
-    passports.iter().filter(|p| check_valid1(&p)).count()
+fn part_one(passports: &[Passport], schema: &Schema) -> usize {
+    passports.iter().filter(|p| schema.has_required(p)).count()
 }
 
-fn part_two(passports: &[HashMap<&str, &str>]) -> usize {
-    passports.iter().filter(|p| check_valid2(&p)).count()
+fn part_two(passports: &[Passport], schema: &Schema) -> usize {
+    passports.iter().filter(|p| schema.is_valid(p)).count()
 }
 
 #[test]
 fn test_examples() {
+    let schema = parse_schema(include_str!("../../schema/04"));
     let input = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
 byr:1937 iyr:2017 cid:147 hgt:183cm
 
@@ -107,8 +441,8 @@ hgt:179cm
 
 hcl:#cfa07d eyr:2025 pid:166559648
 iyr:2011 ecl:brn hgt:59in";
-    let passports = parse_input(&input);
-    assert_eq!(part_one(&passports), 2);
+    let passports = parse_input(input);
+    assert_eq!(part_one(&passports, &schema), 2);
 
     let part2_invalid = "eyr:1972 cid:100
 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
@@ -123,8 +457,8 @@ ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277
 hgt:59cm ecl:zzz
 eyr:2038 hcl:74454a iyr:2023
 pid:3556412378 byr:2007";
-    let passports = parse_input(&part2_invalid);
-    assert_eq!(part_two(&passports), 0);
+    let passports = parse_input(part2_invalid);
+    assert_eq!(part_two(&passports, &schema), 0);
 
     let part2_valid = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
 hcl:#623a2f
@@ -138,6 +472,125 @@ pid:545766238 ecl:hzl
 eyr:2022
 
 iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
-    let passports = parse_input(&part2_valid);
-    assert_eq!(part_two(&passports), 4);
+    let passports = parse_input(part2_valid);
+    assert_eq!(part_two(&passports, &schema), 4);
+}
+
+#[test]
+fn test_schema() {
+    let schema = parse_schema(
+        "# comment
+         size required unit m 1 10 km 1 2
+         colour optional enum red green
+         code required regex [A-Z]{2}",
+    );
+    let passports = parse_input("size:5m code:AB\n\nsize:2km colour:red code:XY");
+    assert_eq!(part_two(&passports, &schema), 2);
+    let passports = parse_input("size:3km code:AB\n\nsize:5m code:ABC\n\nsize:5 code:AB");
+    assert_eq!(part_one(&passports, &schema), 3);
+    assert_eq!(part_two(&passports, &schema), 0);
+    // Unknown fields are invalid
+    let passports = parse_input("size:5m code:AB other:1");
+    assert_eq!(part_two(&passports, &schema), 0);
+
+    assert!(Schema::from_str("byr sometimes any").is_err());
+    assert!(Schema::from_str("byr required int 1 two").is_err());
+    assert!(Schema::from_str("hgt required unit cm 1").is_err());
+    assert!(Schema::from_str("hcl required regex [a-").is_err());
+}
+
+#[test]
+fn test_diagnostics() {
+    let schema = parse_schema(include_str!("../../schema/04"));
+    let passports = parse_input(
+        "byr:abc iyr:2009 eyr:2020 hgt:170 hcl:#12345g ecl:red foo:bar junk
+
+byr:1937 iyr:2017 eyr:2020 hgt:59in hcl:#fffffd ecl:gry pid:860033327",
+    );
+    let problems = schema.validate(&passports[0]);
+    assert_eq!(
+        problems,
+        vec![
+            (
+                "byr",
+                Problem::Malformed("abc is not an integer".to_string())
+            ),
+            (
+                "iyr",
+                Problem::OutOfRange("2009 is not in 2010..=2020".to_string())
+            ),
+            (
+                "hgt",
+                Problem::Malformed("170 doesn't end in one of cm, in".to_string())
+            ),
+            (
+                "hcl",
+                Problem::Malformed("#12345g doesn't match ^(?:#[0-9a-f]{6})$".to_string())
+            ),
+            (
+                "ecl",
+                Problem::OutOfRange(
+                    "red is not one of amb, blu, brn, gry, grn, hzl, oth".to_string()
+                )
+            ),
+            ("pid", Problem::Missing),
+            ("foo", Problem::UnknownKey),
+            (
+                "junk",
+                Problem::Malformed("junk is not of the form key:value".to_string())
+            ),
+        ]
+    );
+    assert!(schema.validate(&passports[1]).is_empty());
+    assert_eq!(part_one(&passports, &schema), 1);
+    assert_eq!(part_two(&passports, &schema), 1);
+    assert_eq!(
+        report(&passports, &schema).lines().nth(1),
+        Some("Passport 2: valid")
+    );
+    assert!(report(&passports, &schema).starts_with(
+        "Passport 1: byr malformed: abc is not an integer; iyr out of range: 2009 is not in"
+    ));
+}
+
+#[test]
+fn test_streaming() {
+    let schema = parse_schema(include_str!("../../schema/04"));
+    let input = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm
+
+
+iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884
+hcl:#cfa07d byr:1929
+\t
+hcl:#ae17e1 iyr:2013
+eyr:2024
+ecl:brn pid:760753108 byr:1931
+hgt:179cm
+
+hcl:#cfa07d eyr:2025 pid:166559648
+iyr:2011 ecl:brn hgt:59in
+";
+    let all: Vec<String> = records(input.as_bytes()).map(Result::unwrap).collect();
+    assert_eq!(all.len(), 4);
+    assert_eq!(
+        all[1],
+        "iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884\nhcl:#cfa07d byr:1929\n"
+    );
+    assert_eq!(count_valid(input.as_bytes(), &schema, 1).unwrap(), (2, 2));
+    assert_eq!(split_records(input), all);
+    let passports = parse_input(input);
+    assert_eq!(part_one(&passports, &schema), 2);
+    assert_eq!(part_two(&passports, &schema), 2);
+
+    // Enough passports for several batches
+    let many = input.repeat(1000);
+    assert_eq!(
+        count_valid(many.as_bytes(), &schema, 1).unwrap(),
+        (2000, 2000)
+    );
+    assert_eq!(
+        count_valid(many.as_bytes(), &schema, 4).unwrap(),
+        (2000, 2000)
+    );
 }
@@ -1,50 +1,439 @@
+use std::ops::Range;
+use std::str::FromStr;
 use std::time::Instant;
 
-fn get_seat_id(input: &str) -> u16 {
-    // The seat id is simply the FBLR representation mapped to 0 and 1 and
-    // interpreted as a binary number.
-    input
-        .chars()
-        .map(|c| match c {
-            'F' | 'L' => 0,
-            'B' | 'R' => 1,
-            _ => unreachable!(),
-        })
-        .fold(0, |acc, digit| acc * 2 + digit)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Axis {
+    // The character meaning "take the lower half", and the one meaning "take
+    // the upper half"
+    lower: char,
+    upper: char,
+    bits: u32,
+}
+
+impl Axis {
+    fn size(&self) -> u32 {
+        1 << self.bits
+    }
+}
+
+// Seat maps keep a flag for every seat, so cap layouts at 16M seats
+const MAX_BITS: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Layout {
+    row: Axis,
+    col: Axis,
+}
+
+// The layout of the plane in the puzzle - 128 rows of 8 seats
+const PLANE: Layout = Layout {
+    row: Axis {
+        lower: 'F',
+        upper: 'B',
+        bits: 7,
+    },
+    col: Axis {
+        lower: 'L',
+        upper: 'R',
+        bits: 3,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Seat {
+    row: u32,
+    col: u32,
+    id: u32,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        // Of the form <lower><upper><bits>,<lower><upper><bits> for the rows
+        // then the columns, e.g. FB7,LR3 for the puzzle's plane.
+        let parse_axis = |axis: &str| -> Result<Axis, String> {
+            let mut chars = axis.chars();
+            match (chars.next(), chars.next(), chars.as_str().parse()) {
+                (Some(lower), Some(upper), Ok(bits)) if lower != upper => {
+                    Ok(Axis { lower, upper, bits })
+                }
+                _ => Err(format!("Bad axis {}", axis)),
+            }
+        };
+        let (row, col) = input
+            .split_once(',')
+            .ok_or_else(|| format!("Expected two axes in {}", input))?;
+        let layout = Layout {
+            row: parse_axis(row)?,
+            col: parse_axis(col)?,
+        };
+        // The number of seats must fit in a u32
+        if layout.row.bits + layout.col.bits >= 32 {
+            return Err(format!("Too many bits in {}", input));
+        }
+        Ok(layout)
+    }
+}
+
+impl Layout {
+    fn seat(&self, row: u32, col: u32) -> Seat {
+        Seat {
+            row,
+            col,
+            id: row * self.col.size() + col,
+        }
+    }
+
+    fn decode(&self, input: &str) -> Result<Seat, String> {
+        // Each axis is simply its characters mapped to 0 and 1 and interpreted
+        // as a binary number.
+        let chars: Vec<char> = input.chars().collect();
+        let len = (self.row.bits + self.col.bits) as usize;
+        if chars.len() != len {
+            return Err(format!(
+                "Expected {} characters, got {} in {}",
+                len,
+                chars.len(),
+                input
+            ));
+        }
+        let (row_chars, col_chars) = chars.split_at(self.row.bits as usize);
+        let decode_axis = |axis: &Axis, chars: &[char], offset: usize| {
+            chars
+                .iter()
+                .enumerate()
+                .try_fold(0, |acc, (i, &c)| match c {
+                    c if c == axis.lower => Ok(acc * 2),
+                    c if c == axis.upper => Ok(acc * 2 + 1),
+                    _ => Err(format!(
+                        "Unexpected character {} at position {} of {}",
+                        c,
+                        offset + i + 1,
+                        input
+                    )),
+                })
+        };
+        let row = decode_axis(&self.row, row_chars, 0)?;
+        let col = decode_axis(&self.col, col_chars, row_chars.len())?;
+        Ok(self.seat(row, col))
+    }
+
+    fn encode(&self, row: u32, col: u32) -> Result<String, String> {
+        if row >= self.row.size() || col >= self.col.size() {
+            return Err(format!("No seat at row {}, column {}", row, col));
+        }
+        let encode_axis = |axis: Axis, n: u32| {
+            (0..axis.bits).rev().map(move |bit| {
+                if n & (1 << bit) == 0 {
+                    axis.lower
+                } else {
+                    axis.upper
+                }
+            })
+        };
+        Ok(encode_axis(self.row, row)
+            .chain(encode_axis(self.col, col))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Block {
+    // A run of len empty seats in a row, starting at column col
+    row: u32,
+    col: u32,
+    len: u32,
+}
+
+struct SeatMap {
+    layout: Layout,
+    // Indexed by seat id
+    occupied: Vec<bool>,
+}
+
+impl SeatMap {
+    fn new(layout: Layout, seats: &[Seat]) -> Result<Self, String> {
+        let bits = layout.row.bits.saturating_add(layout.col.bits);
+        if bits > MAX_BITS {
+            return Err(format!(
+                "Too many seats to map with {} bits (at most {} allowed)",
+                bits, MAX_BITS
+            ));
+        }
+        let mut occupied = vec![false; (layout.row.size() * layout.col.size()) as usize];
+        for seat in seats {
+            occupied[seat.id as usize] = true;
+        }
+        Ok(SeatMap { layout, occupied })
+    }
+
+    fn row(&self, row: u32) -> &[bool] {
+        let cols = self.layout.col.size() as usize;
+        &self.occupied[(row as usize * cols)..((row as usize + 1) * cols)]
+    }
+
+    fn existing_rows(&self) -> Range<u32> {
+        // Some of the rows at the very front and back of the plane don't exist -
+        // take these to be the rows before the first occupied seat and after the
+        // last.
+        let rows = 0..self.layout.row.size();
+        let first = rows.clone().find(|&r| self.row(r).contains(&true));
+        let last = rows.clone().rev().find(|&r| self.row(r).contains(&true));
+        match (first, last) {
+            (Some(first), Some(last)) => first..(last + 1),
+            _ => 0..0,
+        }
+    }
+
+    fn missing_rows(&self) -> (Range<u32>, Range<u32>) {
+        // The missing rows at the front and the back of the plane
+        let existing = self.existing_rows();
+        if existing.is_empty() {
+            return (0..self.layout.row.size(), 0..0);
+        }
+        (0..existing.start, existing.end..self.layout.row.size())
+    }
+
+    fn empty_seats(&self) -> Vec<Seat> {
+        self.free_blocks()
+            .iter()
+            .flat_map(|b| (b.col..(b.col + b.len)).map(move |col| self.layout.seat(b.row, col)))
+            .collect()
+    }
+
+    fn free_blocks(&self) -> Vec<Block> {
+        // Every run of adjacent empty seats in each of the rows which exist
+        let mut blocks = Vec::new();
+        for row in self.existing_rows() {
+            let mut start = None;
+            for (col, &occupied) in self.row(row).iter().chain(&[true]).enumerate() {
+                match (start, occupied) {
+                    (None, false) => start = Some(col as u32),
+                    (Some(s), true) => {
+                        blocks.push(Block {
+                            row,
+                            col: s,
+                            len: col as u32 - s,
+                        });
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        blocks
+    }
+
+    fn blocks_for_group(&self, size: u32) -> Vec<Block> {
+        // Everywhere a group of the given size can sit together in one row
+        self.free_blocks()
+            .into_iter()
+            .filter(|b| b.len >= size)
+            .collect()
+    }
+
+    fn render(&self) -> String {
+        // One line per row - # for an occupied seat, . for an empty seat and
+        // - for a seat in a row which doesn't exist
+        let existing = self.existing_rows();
+        let width = (self.layout.row.size() - 1).to_string().len();
+        let mut output = String::new();
+        for row in 0..self.layout.row.size() {
+            output.push_str(&format!("{:>width$} ", row, width = width));
+            output.extend(self.row(row).iter().map(|&occupied| {
+                match (existing.contains(&row), occupied) {
+                    (false, _) => '-',
+                    (true, true) => '#',
+                    (true, false) => '.',
+                }
+            }));
+            output.push('\n');
+        }
+        output
+    }
 }
 
 fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
-    let input = std::fs::read_to_string("input/05")?;
-    let seats = parse_input(&input);
-    println!("Part 1: {}", part_one(&seats));
-    println!("Part 2: {}", part_two(&seats));
+    let mut args: Vec<String> = std::env::args().collect();
+    // e.g. `--layout AB9,CD4` before any of the commands below for a plane with
+    // 512 rows of 16 seats
+    let mut layout = PLANE;
+    if let Some(i) = args.iter().position(|a| a == "--layout") {
+        layout = args.get(i + 1).expect("Expected a layout").parse().unwrap();
+        args.drain(i..(i + 2));
+    }
+    match args.get(1).map(String::as_str) {
+        Some("decode") => {
+            // e.g. `cargo run --bin 05 -- decode BFFFBBFRRR`
+            for seat in &args[2..] {
+                println!("{}: {:?}", seat, layout.decode(seat).unwrap());
+            }
+        }
+        Some("encode") => {
+            // e.g. `cargo run --bin 05 -- encode 70 7`
+            let row = args.get(2).expect("Expected a row").parse().unwrap();
+            let col = args.get(3).expect("Expected a column").parse().unwrap();
+            println!("{}", layout.encode(row, col).unwrap());
+        }
+        Some("map") => {
+            // e.g. `cargo run --bin 05 -- map 4` to draw the plane and list
+            // everywhere a group of 4 can sit together
+            let input = std::fs::read_to_string("input/05")?;
+            let seat_map = SeatMap::new(layout, &parse_input(&input, &layout)).unwrap();
+            print!("{}", seat_map.render());
+            let (front, back) = seat_map.missing_rows();
+            println!("Missing rows: {:?} and {:?}", front, back);
+            println!("Empty seats: {}", seat_map.empty_seats().len());
+            let size = args.get(2).map_or(1, |s| s.parse().unwrap());
+            for block in seat_map.blocks_for_group(size) {
+                println!(
+                    "Row {}, columns {} to {}",
+                    block.row,
+                    block.col,
+                    block.col + block.len - 1
+                );
+            }
+        }
+        _ => {
+            let input = std::fs::read_to_string("input/05")?;
+            let seats = parse_input(&input, &layout);
+            println!("Part 1: {}", part_one(&seats));
+            println!(
+                "Part 2: {}",
+                part_two(&SeatMap::new(layout, &seats).unwrap())
+            );
+        }
+    }
     println!("Time: {}µs", now.elapsed().as_micros());
     Ok(())
 }
 
-fn parse_input(input: &str) -> Vec<u16> {
-    input.lines().map(|l| get_seat_id(l)).collect()
+fn parse_input(input: &str, layout: &Layout) -> Vec<Seat> {
+    input.lines().map(|l| layout.decode(l).unwrap()).collect()
 }
 
-fn part_one(seats: &[u16]) -> u16 {
-    *seats.iter().max().unwrap()
+fn part_one(seats: &[Seat]) -> u32 {
+    seats.iter().map(|s| s.id).max().unwrap()
 }
 
-fn part_two(seats: &[u16]) -> u16 {
-    // Our seat is between the minimum and the maximum. Find the id in the
-    // slice for which id + 2 is in the slice, but not id + 1. Then, id + 1 is
-    // our seat. Not worth building a HashSet for such a short input.
-    seats
+fn part_two(seat_map: &SeatMap) -> u32 {
+    // Our seat is the only empty seat with occupied seats either side of it
+    // (by id).
+    seat_map
+        .empty_seats()
         .iter()
-        .find(|&id| !seats.contains(&(id + 1)) && seats.contains(&(id + 2)))
+        .find(|s| {
+            let occupied = |id: Option<u32>| id.and_then(|id| seat_map.occupied.get(id as usize));
+            occupied(s.id.checked_sub(1)) == Some(&true) && occupied(Some(s.id + 1)) == Some(&true)
+        })
         .unwrap()
-        + 1
+        .id
 }
 
 #[test]
 fn test_examples() {
-    assert_eq!(get_seat_id(&"BFFFBBFRRR"), 567);
-    assert_eq!(get_seat_id(&"FFFBBBFRRR"), 119);
-    assert_eq!(get_seat_id(&"BBFFBBFRLL"), 820);
+    assert_eq!(PLANE.decode("BFFFBBFRRR").unwrap().id, 567);
+    assert_eq!(PLANE.decode("FFFBBBFRRR").unwrap().id, 119);
+    assert_eq!(PLANE.decode("BBFFBBFRLL").unwrap().id, 820);
+}
+
+#[test]
+fn test_layouts() {
+    assert_eq!(
+        PLANE.decode("BFFFBBFRRR"),
+        Ok(Seat {
+            row: 70,
+            col: 7,
+            id: 567
+        })
+    );
+    assert_eq!(PLANE.encode(70, 7).unwrap(), "BFFFBBFRRR");
+    assert_eq!(
+        PLANE.encode(128, 0),
+        Err("No seat at row 128, column 0".to_string())
+    );
+    assert_eq!(
+        PLANE.decode("BFFFBBFRR"),
+        Err("Expected 10 characters, got 9 in BFFFBBFRR".to_string())
+    );
+    assert_eq!(
+        PLANE.decode("BFFFBBFRXR"),
+        Err("Unexpected character X at position 9 of BFFFBBFRXR".to_string())
+    );
+    // Row characters aren't allowed in the columns
+    assert!(PLANE.decode("BFFFBBFRRF").is_err());
+
+    assert_eq!("FB7,LR3".parse::<Layout>(), Ok(PLANE));
+    assert!("FB7".parse::<Layout>().is_err());
+    assert!("FF7,LR3".parse::<Layout>().is_err());
+    assert!("FB30,LR3".parse::<Layout>().is_err());
+    assert!("FB20,LR11".parse::<Layout>().is_ok());
+
+    // A wide-bodied plane with 32 rows of 16 seats, using digits
+    let layout: Layout = "015,014".parse().unwrap();
+    for (row, col) in [(0, 0), (31, 15), (17, 9)] {
+        let seat = layout.decode(&layout.encode(row, col).unwrap()).unwrap();
+        assert_eq!(seat, layout.seat(row, col));
+        assert_eq!(seat.id, row * 16 + col);
+    }
+    assert_eq!(layout.encode(17, 9).unwrap(), "100011001");
+}
+
+#[test]
+fn test_seat_map() {
+    // 8 rows of 4 seats, with the first and last two rows missing
+    let layout: Layout = "FB3,LR2".parse().unwrap();
+    let mut seats = Vec::new();
+    for row in 2..6 {
+        for col in 0..4 {
+            seats.push(layout.seat(row, col));
+        }
+    }
+    // Free up the back row apart from one seat, our seat and a pair of seats
+    seats.retain(|s| !(s.row == 5 && s.col != 2) && s.id != 13 && !(s.row == 2 && s.col >= 2));
+    let seat_map = SeatMap::new(layout, &seats).unwrap();
+    assert_eq!(seat_map.missing_rows(), (0..2, 6..8));
+    assert_eq!(
+        seat_map.free_blocks(),
+        vec![
+            Block {
+                row: 2,
+                col: 2,
+                len: 2
+            },
+            Block {
+                row: 3,
+                col: 1,
+                len: 1
+            },
+            Block {
+                row: 5,
+                col: 0,
+                len: 2
+            },
+            Block {
+                row: 5,
+                col: 3,
+                len: 1
+            },
+        ]
+    );
+    assert_eq!(seat_map.empty_seats().len(), 6);
+    assert_eq!(seat_map.blocks_for_group(2).len(), 2);
+    assert!(seat_map.blocks_for_group(3).is_empty());
+    assert_eq!(part_two(&seat_map), 13);
+    assert_eq!(
+        seat_map.render(),
+        "0 ----\n1 ----\n2 ##..\n3 #.##\n4 ####\n5 ..#.\n6 ----\n7 ----\n"
+    );
+    // Layouts too big to map can still decode seats
+    let huge: Layout = "FB20,LR11".parse().unwrap();
+    let seat = huge.decode(&huge.encode(1 << 19, 5).unwrap()).unwrap();
+    assert_eq!(seat.id, (1 << 30) + 5);
+    assert!(SeatMap::new(huge, &[seat]).is_err());
+    let mut built = PLANE;
+    built.row.bits = u32::MAX;
+    assert!(SeatMap::new(built, &[]).is_err());
 }
@@ -1,49 +1,423 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::time::Instant;
 
+struct Alphabet {
+    // Maps each possible answer to its index in an AnswerSet, and back again
+    indices: HashMap<char, usize>,
+    answers: Vec<char>,
+}
+
+impl Alphabet {
+    fn new(answers: impl IntoIterator<Item = char>) -> Self {
+        let mut indices = HashMap::new();
+        let mut unique = Vec::new();
+        for answer in answers {
+            if let Entry::Vacant(entry) = indices.entry(answer) {
+                entry.insert(unique.len());
+                unique.push(answer);
+            }
+        }
+        Alphabet {
+            indices,
+            answers: unique,
+        }
+    }
+
+    fn index(&self, answer: char) -> Option<usize> {
+        self.indices.get(&answer).copied()
+    }
+
+    fn answer(&self, index: usize) -> char {
+        self.answers[index]
+    }
+
+    fn len(&self) -> usize {
+        self.answers.len()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct AnswerSet {
+    // A bitset, growing as answers with higher indices are added
+    words: Vec<u64>,
+}
+
+impl AnswerSet {
+    fn insert(&mut self, index: usize) {
+        let (word, bit) = (index / 64, index % 64);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << bit;
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.words
+            .get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    fn union_with(&mut self, other: &AnswerSet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other_word) in self.words.iter_mut().zip(&other.words) {
+            *word |= other_word;
+        }
+    }
+
+    fn intersect_with(&mut self, other: &AnswerSet) {
+        self.words.truncate(other.words.len());
+        for (word, other_word) in self.words.iter_mut().zip(&other.words) {
+            *word &= other_word;
+        }
+    }
+
+    fn symmetric_difference_with(&mut self, other: &AnswerSet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other_word) in self.words.iter_mut().zip(&other.words) {
+            *word ^= other_word;
+        }
+    }
+
+    fn len(&self) -> u32 {
+        self.words.iter().map(|word| word.count_ones()).sum()
+    }
+}
+
+impl PartialEq for AnswerSet {
+    fn eq(&self, other: &Self) -> bool {
+        // Unions and differences can leave trailing empty words behind, which
+        // don't change the set
+        let (shorter, longer) = match self.words.len() <= other.words.len() {
+            true => (&self.words, &other.words),
+            false => (&other.words, &self.words),
+        };
+        longer[..shorter.len()] == shorter[..] && longer[shorter.len()..].iter().all(|&w| w == 0)
+    }
+}
+
+impl Eq for AnswerSet {}
+
+// One AnswerSet per person in the group
+type Group = Vec<AnswerSet>;
+
+fn answer_counts(group: &Group) -> Vec<usize> {
+    // The number of people in the group who gave each answer
+    let num_answers = group.iter().map(|p| p.words.len() * 64).max().unwrap_or(0);
+    (0..num_answers)
+        .map(|i| group.iter().filter(|p| p.contains(i)).count())
+        .collect()
+}
+
+fn answered_by(group: &Group, predicate: impl Fn(usize) -> bool) -> AnswerSet {
+    // The answers for which the number of people in the group who gave them
+    // satisfies the predicate
+    let mut set = AnswerSet::default();
+    for (i, &count) in answer_counts(group).iter().enumerate() {
+        if count > 0 && predicate(count) {
+            set.insert(i);
+        }
+    }
+    set
+}
+
+fn at_least(group: &Group, k: usize) -> AnswerSet {
+    answered_by(group, |count| count >= k)
+}
+
+fn exactly_one(group: &Group) -> AnswerSet {
+    answered_by(group, |count| count == 1)
+}
+
+fn symmetric_difference(group: &Group) -> AnswerSet {
+    // The answers given by an odd number of people in the group
+    group.iter().fold(AnswerSet::default(), |mut acc, person| {
+        acc.symmetric_difference_with(person);
+        acc
+    })
+}
+
+#[derive(Debug, PartialEq)]
+struct GroupStats {
+    size: usize,
+    anyone: u32,
+    everyone: u32,
+    at_least: u32,
+    exactly_one: u32,
+    symmetric_difference: u32,
+}
+
+#[derive(Debug, PartialEq)]
+struct QuestionStats {
+    answer: char,
+    // The total number of people who gave this answer, the number of groups in
+    // which anyone gave it, and the number in which everyone gave it
+    people: usize,
+    groups_anyone: usize,
+    groups_everyone: usize,
+}
+
+struct Analysis {
+    // at_least in each GroupStats counts the answers given by at least k
+    // people in the group
+    k: usize,
+    groups: Vec<GroupStats>,
+    questions: Vec<QuestionStats>,
+    most_common: Vec<char>,
+    least_common: Vec<char>,
+}
+
+fn analyse(groups: &[Group], alphabet: &Alphabet, k: usize) -> Analysis {
+    let group_stats = groups
+        .iter()
+        .map(|g| GroupStats {
+            size: g.len(),
+            anyone: at_least(g, 1).len(),
+            everyone: at_least(g, g.len()).len(),
+            at_least: at_least(g, k).len(),
+            exactly_one: exactly_one(g).len(),
+            symmetric_difference: symmetric_difference(g).len(),
+        })
+        .collect();
+
+    let mut questions: Vec<QuestionStats> = (0..alphabet.len())
+        .map(|i| QuestionStats {
+            answer: alphabet.answer(i),
+            people: 0,
+            groups_anyone: 0,
+            groups_everyone: 0,
+        })
+        .collect();
+    for group in groups {
+        for (i, &count) in answer_counts(group).iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            questions[i].people += count;
+            questions[i].groups_anyone += 1;
+            if count == group.len() {
+                questions[i].groups_everyone += 1;
+            }
+        }
+    }
+
+    // Every answer which ties for the most (or least) common
+    let with_count = |count: Option<usize>| -> Vec<char> {
+        questions
+            .iter()
+            .filter(|q| Some(q.people) == count)
+            .map(|q| q.answer)
+            .collect()
+    };
+    let most_common = with_count(questions.iter().map(|q| q.people).max());
+    let least_common = with_count(questions.iter().map(|q| q.people).min());
+    Analysis {
+        k,
+        groups: group_stats,
+        questions,
+        most_common,
+        least_common,
+    }
+}
+
+impl Analysis {
+    fn to_table(&self) -> String {
+        let mut output = format!(
+            "{:>6} {:>5} {:>7} {:>9} {:>11} {:>12} {:>10}\n",
+            "Group",
+            "Size",
+            "Anyone",
+            "Everyone",
+            format!("At least {}", self.k),
+            "Exactly one",
+            "Odd number"
+        );
+        for (i, g) in self.groups.iter().enumerate() {
+            output.push_str(&format!(
+                "{:>6} {:>5} {:>7} {:>9} {:>11} {:>12} {:>10}\n",
+                i + 1,
+                g.size,
+                g.anyone,
+                g.everyone,
+                g.at_least,
+                g.exactly_one,
+                g.symmetric_difference
+            ));
+        }
+        output.push('\n');
+        output.push_str(&format!(
+            "{:>6} {:>7} {:>14} {:>16}\n",
+            "Answer", "People", "Groups (any)", "Groups (every)"
+        ));
+        for q in &self.questions {
+            output.push_str(&format!(
+                "{:>6} {:>7} {:>14} {:>16}\n",
+                q.answer, q.people, q.groups_anyone, q.groups_everyone
+            ));
+        }
+        output.push('\n');
+        let join = |answers: &[char]| {
+            answers
+                .iter()
+                .map(char::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        output.push_str(&format!("Most common: {}\n", join(&self.most_common)));
+        output.push_str(&format!("Least common: {}\n", join(&self.least_common)));
+        output
+    }
+
+    fn to_json(&self) -> String {
+        let groups: Vec<String> = self
+            .groups
+            .iter()
+            .map(|g| {
+                format!(
+                    "{{\"size\":{},\"anyone\":{},\"everyone\":{},\"at_least\":{},\"exactly_one\":{},\"symmetric_difference\":{}}}",
+                    g.size, g.anyone, g.everyone, g.at_least, g.exactly_one, g.symmetric_difference
+                )
+            })
+            .collect();
+        let questions: Vec<String> = self
+            .questions
+            .iter()
+            .map(|q| {
+                format!(
+                    "{{\"answer\":{},\"people\":{},\"groups_anyone\":{},\"groups_everyone\":{}}}",
+                    json_string(q.answer),
+                    q.people,
+                    q.groups_anyone,
+                    q.groups_everyone
+                )
+            })
+            .collect();
+        let answers = |answers: &[char]| {
+            answers
+                .iter()
+                .map(|&a| json_string(a))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!(
+            "{{\"k\":{},\"groups\":[{}],\"questions\":[{}],\"most_common\":[{}],\"least_common\":[{}]}}",
+            self.k,
+            groups.join(","),
+            questions.join(","),
+            answers(&self.most_common),
+            answers(&self.least_common)
+        )
+    }
+}
+
+fn json_string(c: char) -> String {
+    match c {
+        '"' => "\"\\\"\"".to_string(),
+        '\\' => "\"\\\\\"".to_string(),
+        c if c.is_control() => format!("\"\\u{:04x}\"", c as u32),
+        c => format!("\"{}\"", c),
+    }
+}
+
 fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
     let input = std::fs::read_to_string("input/06")?;
-    println!("Part 1: {}", part_one(&input));
-    println!("Part 2: {}", part_two(&input));
+    // e.g. `cargo run --bin 06 -- --alphabet abcdefαβγ` if the questions aren't
+    // just a through z
+    let mut args: Vec<String> = std::env::args().collect();
+    let alphabet = match args.iter().position(|a| a == "--alphabet") {
+        Some(i) => {
+            let alphabet = Alphabet::new(args.get(i + 1).expect("Expected an alphabet").chars());
+            args.drain(i..(i + 2));
+            alphabet
+        }
+        None => Alphabet::new('a'..='z'),
+    };
+    let groups = parse_input(&input, &alphabet).unwrap();
+    println!("Part 1: {}", part_one(&groups));
+    println!("Part 2: {}", part_two(&groups));
+    if args.get(1).map(String::as_str) == Some("stats") {
+        // e.g. `cargo run --bin 06 -- stats 2 json` to analyse each group and
+        // question, including the answers given by at least 2 people
+        let k = args.get(2).map_or(2, |k| k.parse().unwrap());
+        let analysis = analyse(&groups, &alphabet, k);
+        match args.get(3).map(String::as_str) {
+            Some("json") => println!("{}", analysis.to_json()),
+            _ => print!("{}", analysis.to_table()),
+        }
+    }
     println!("Time: {}µs", now.elapsed().as_micros());
     Ok(())
 }
 
-fn part_one(input: &str) -> u32 {
-    // Bit twiddling solution - map each answer ('a' through 'z') to a u32
-    // bitfield. (Choose u32 because 32 > 26).
-    // We can simply OR all of these u32s together, which will give us the set
-    // of all the answers for a group.
-    // Counting ones then counts total answers for a group.
+fn parse_input(input: &str, alphabet: &Alphabet) -> Result<Vec<Group>, String> {
+    // Groups are separated by blank lines, and each line of a group is one
+    // person's answers.
+    let mut groups = Vec::new();
+    let mut group = Vec::new();
+    for (line_num, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            if !group.is_empty() {
+                groups.push(group);
+                group = Vec::new();
+            }
+            continue;
+        }
+        let mut person = AnswerSet::default();
+        for answer in line.trim().chars() {
+            match alphabet.index(answer) {
+                Some(index) => person.insert(index),
+                None => {
+                    return Err(format!(
+                        "Unknown answer {} on line {}",
+                        answer,
+                        line_num + 1
+                    ))
+                }
+            }
+        }
+        group.push(person);
+    }
+    if !group.is_empty() {
+        groups.push(group);
+    }
+    Ok(groups)
+}
+
+fn part_one(groups: &[Group]) -> u32 {
+    // Take the union of everyone's answers in each group - the questions to
+    // which anyone answered yes.
     // Sum all the group totals together.
-    input
-        .split("\n\n")
+    groups
+        .iter()
         .map(|g| {
-            g.bytes()
-                .filter(|&x| x != b'\n')
-                .fold(u32::MIN, |acc, answer| acc | (1u32 << (answer - b'a')))
-                .count_ones()
+            g.iter()
+                .fold(AnswerSet::default(), |mut acc, person| {
+                    acc.union_with(person);
+                    acc
+                })
+                .len()
         })
         .sum()
 }
 
-fn part_two(input: &str) -> u32 {
-    // Bit twiddling solution - map each answer ('a' through 'z') to a u32
-    // bitfield.
-    // For each line (person) in a group, OR these together.
-    // AND all of the lines in a group together to get the common answers.
-    // Counting ones then counts total answers for a group.
+fn part_two(groups: &[Group]) -> u32 {
+    // Take the intersection of everyone's answers in each group - the
+    // questions to which everyone answered yes.
     // Sum all the group totals together.
-    input
-        .split("\n\n")
+    groups
+        .iter()
         .map(|g| {
-            g.lines()
-                .map(|l| {
-                    l.bytes()
-                        .fold(u32::MIN, |acc, answer| acc | (1u32 << (answer - b'a')))
-                })
-                .fold(u32::MAX, |acc, person| acc & person)
-                .count_ones()
+            let mut people = g.iter();
+            let mut common = people.next().cloned().unwrap_or_default();
+            for person in people {
+                common.intersect_with(person);
+            }
+            common.len()
         })
         .sum()
 }
@@ -65,6 +439,113 @@ a
 a
 
 b";
-    assert_eq!(part_one(&input), 11);
-    assert_eq!(part_two(&input), 6);
+    let groups = parse_input(input, &Alphabet::new('a'..='z')).unwrap();
+    assert_eq!(part_one(&groups), 11);
+    assert_eq!(part_two(&groups), 6);
+}
+
+#[test]
+fn test_alphabets() {
+    // More than 64 possible answers, including some outside of ASCII
+    let alphabet = Alphabet::new(('a'..='z').chain('A'..='Z').chain('α'..='ω'));
+    let input = "aωZ\nωa\n\n\n\nψ\nψb\nψ";
+    let groups = parse_input(input, &alphabet).unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0][0].words.len(), 2);
+    assert_eq!(groups[1][1].len(), 2);
+    assert_eq!(part_one(&groups), 5);
+    assert_eq!(part_two(&groups), 3);
+
+    // Answers outside the first word cancel out, leaving an empty word behind
+    let mut set = groups[0][0].clone();
+    set.symmetric_difference_with(&groups[0][0]);
+    assert_eq!(set.words.len(), 2);
+    assert_eq!(set, AnswerSet::default());
+    let mut b = AnswerSet::default();
+    b.insert(1);
+    let mut with_psi = b.clone();
+    with_psi.insert(alphabet.index('ψ').unwrap());
+    assert_ne!(with_psi, b);
+
+    assert_eq!(
+        parse_input("ab\n\nb!", &alphabet).unwrap_err(),
+        "Unknown answer ! on line 3"
+    );
+    assert_eq!(
+        parse_input("abz", &Alphabet::new("abc".chars())).unwrap_err(),
+        "Unknown answer z on line 1"
+    );
+}
+
+#[test]
+fn test_analysis() {
+    let alphabet = Alphabet::new("abcd\"".chars());
+    let input = "abc
+ab
+a
+
+b
+c
+
+a
+a
+\"";
+    let groups = parse_input(input, &alphabet).unwrap();
+    let members = |set: AnswerSet| (0..5).filter(|&i| set.contains(i)).collect::<Vec<_>>();
+    assert_eq!(members(at_least(&groups[0], 2)), vec![0, 1]);
+    assert_eq!(members(exactly_one(&groups[0])), vec![2]);
+    assert_eq!(members(symmetric_difference(&groups[0])), vec![0, 2]);
+
+    let analysis = analyse(&groups, &alphabet, 2);
+    assert_eq!(
+        analysis.groups,
+        vec![
+            GroupStats {
+                size: 3,
+                anyone: 3,
+                everyone: 1,
+                at_least: 2,
+                exactly_one: 1,
+                symmetric_difference: 2
+            },
+            GroupStats {
+                size: 2,
+                anyone: 2,
+                everyone: 0,
+                at_least: 0,
+                exactly_one: 2,
+                symmetric_difference: 2
+            },
+            GroupStats {
+                size: 3,
+                anyone: 2,
+                everyone: 0,
+                at_least: 1,
+                exactly_one: 1,
+                symmetric_difference: 1
+            },
+        ]
+    );
+    assert_eq!(
+        analysis.questions[0],
+        QuestionStats {
+            answer: 'a',
+            people: 5,
+            groups_anyone: 2,
+            groups_everyone: 1
+        }
+    );
+    assert_eq!(analysis.most_common, vec!['a']);
+    assert_eq!(analysis.least_common, vec!['d']);
+    assert!(analysis
+        .to_table()
+        .contains("Most common: a\nLeast common: d\n"));
+
+    let json = analysis.to_json();
+    assert!(json.starts_with(
+        "{\"k\":2,\"groups\":[{\"size\":3,\"anyone\":3,\"everyone\":1,\"at_least\":2,"
+    ));
+    assert!(json
+        .contains("{\"answer\":\"\\\"\",\"people\":1,\"groups_anyone\":1,\"groups_everyone\":0}"));
+    assert!(json.ends_with("\"most_common\":[\"a\"],\"least_common\":[\"d\"]}"));
 }
@@ -1,98 +1,528 @@
-use aoc2020::find_all_positions;
+use std::cell::OnceCell;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 use std::time::Instant;
 
 type Bags<'a> = HashMap<&'a str, HashSet<(u32, &'a str)>>;
-type ParentBags<'a> = HashMap<&'a str, HashSet<&'a str>>;
+// A bag's colour, and the bags directly inside it
+type Rule<'a> = (&'a str, HashSet<(u32, &'a str)>);
 
 fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
     let input = std::fs::read_to_string("input/07")?;
-    let (bags, parent_bags) = parse_input(&input);
+    let bags =
+        parse_input(&input).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let graph = BagGraph::new(&bags);
     println!("Time: {}µs", now.elapsed().as_micros());
-    println!("Part 1: {}", part_one(&parent_bags));
-    println!("Part 2: {}", part_two(&bags));
+    match (part_one(&graph), part_two(&graph)) {
+        (Ok(one), Ok(two)) => {
+            println!("Part 1: {}", one);
+            println!("Part 2: {}", two);
+        }
+        (Err(e), _) | (_, Err(e)) => println!("{}", e),
+    }
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("query") => {
+            // e.g. `cargo run --bin 07 -- query "light red"`
+            let colour = args.get(2).expect("Expected a colour");
+            match (graph.count_containers(colour), graph.count_inside(colour)) {
+                (Ok(containers), Ok(inside)) => println!(
+                    "{}: {} bags can contain it, and it contains {} bags",
+                    colour, containers, inside
+                ),
+                (Err(e), _) | (_, Err(e)) => println!("{}", e),
+            }
+        }
+        Some("all") => {
+            // Answer both parts for every colour
+            match (graph.ancestors(), graph.inner_counts()) {
+                (Ok(ancestors), Ok(inner_counts)) => {
+                    for (id, colour) in graph.colours.iter().enumerate() {
+                        let inside =
+                            inner_counts[id].map_or("too many".to_string(), |n| n.to_string());
+                        println!(
+                            "{}: {} containers, {} inside",
+                            colour,
+                            ancestors[id].len(),
+                            inside
+                        );
+                    }
+                }
+                (Err(e), _) | (_, Err(e)) => println!("{}", e),
+            }
+        }
+        Some(format @ ("dot" | "json")) => {
+            // e.g. `cargo run --bin 07 -- dot bags.dot "shiny gold" descendants`
+            // to export the bags inside shiny gold bags, with shiny gold
+            // highlighted
+            let path = args.get(2).expect("Expected an output file");
+            let target = args.get(3).map(String::as_str);
+            let restriction = args.get(4).map_or(Restriction::All, |r| r.parse().unwrap());
+            let exported = match format {
+                "dot" => graph.to_dot(target, restriction),
+                _ => graph.to_json(target, restriction),
+            };
+            match exported {
+                Ok(exported) => std::fs::write(path, exported)?,
+                Err(e) => println!("{}", e),
+            }
+        }
+        _ => {}
+    }
     println!("Time: {}µs", now.elapsed().as_micros());
     Ok(())
 }
 
-fn parse_input(input: &str) -> (Bags, ParentBags) {
-    // Possible line formats:
-    // shiny teal bags contain 1 posh green bag, 5 pale indigo bags, 1 mirrored purple bag.
-    // pale coral bags contain no other bags.
+fn parse_input(input: &str) -> Result<Bags<'_>, String> {
+    // Rules are of the form:
+    //   <colour> bag(s) contain <count> <colour> bag(s), <count> <colour> bag(s).
+    //   <colour> bag(s) contain no other bag(s).
+    // where a colour is any number of words and the trailing full stop is
+    // optional. Blank lines are ignored.
     let mut bags = HashMap::new();
-    let mut parent_bags = HashMap::new();
-    for line in input.lines() {
-        let mut pc = line.split(" bags contain ");
-        let parent = pc.next().unwrap();
-        let mut children = HashSet::new();
-        let children_str = pc.next().unwrap();
-        if children_str != "no other bags." {
-            for child in children_str.split(", ") {
-                let mut space_positions = find_all_positions(child, ' ');
-                let num_pos: usize = space_positions.next().unwrap();
-                let post_iden: usize = space_positions.nth(1).unwrap();
-                let num: u32 = child[..num_pos].parse().unwrap();
-                let iden = &child[(num_pos + 1)..post_iden];
-                children.insert((num, iden));
-                parent_bags
-                    .entry(iden)
-                    .or_insert_with(HashSet::new)
-                    .insert(parent);
-            }
+    for (line_num, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let err = |reason: &str| format!("Line {}: {}: {}", line_num + 1, reason, line);
+        let (parent, children) = parse_rule(line).map_err(|reason| err(&reason))?;
+        if bags.contains_key(parent) {
+            return Err(err(&format!("Duplicate rule for {}", parent)));
         }
         bags.insert(parent, children);
     }
-    (bags, parent_bags)
+    Ok(bags)
+}
+
+fn parse_rule(line: &str) -> Result<Rule<'_>, String> {
+    let line = line.trim().trim_end_matches('.');
+    let (parent, children_str) = line.split_once(" contain ").ok_or("Expected \"contain\"")?;
+    let parent = parse_bag(parent)?;
+    let mut children = HashSet::new();
+    if !matches!(children_str.trim(), "no other bags" | "no other bag") {
+        for child in children_str.split(',') {
+            let (num, colour) = child
+                .trim()
+                .split_once(' ')
+                .ok_or_else(|| format!("Expected a number of bags in \"{}\"", child.trim()))?;
+            let num: u32 = num
+                .parse()
+                .map_err(|_| format!("Bad number of bags \"{}\"", num))?;
+            children.insert((num, parse_bag(colour)?));
+        }
+    }
+    Ok((parent, children))
+}
+
+fn parse_bag(input: &str) -> Result<&str, String> {
+    // A colour followed by "bag" or "bags"
+    let input = input.trim();
+    let colour = input
+        .strip_suffix("bags")
+        .or_else(|| input.strip_suffix("bag"))
+        .filter(|colour| colour.is_empty() || colour.ends_with(' '))
+        .ok_or_else(|| format!("Expected \"bag\" or \"bags\" after \"{}\"", input))?
+        .trim();
+    if colour.is_empty() {
+        return Err(format!("Missing colour in \"{}\"", input));
+    }
+    Ok(colour)
+}
+
+type BagId = usize;
+
+struct BagGraph<'a> {
+    // Colours are interned - each has a BagId which indexes into the Vecs below
+    colours: Vec<&'a str>,
+    ids: HashMap<&'a str, BagId>,
+    // The bags directly inside each bag, with how many of each
+    contents: Vec<Vec<(u32, BagId)>>,
+    // The bags which directly contain each bag
+    containers: Vec<Vec<BagId>>,
+    // Answers for every colour at once, calculated on first use. A count of
+    // None means that there are too many bags inside to count.
+    inner_counts: OnceCell<Result<Vec<Option<u64>>, BagError>>,
+    ancestors: OnceCell<Result<Vec<BitSet>, BagError>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BagError {
+    UnknownColour(String),
+    // A path of colours which each contain the next, starting and ending with
+    // the same colour
+    Cycle(Vec<String>),
+    Overflow(String),
+}
+
+impl fmt::Display for BagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BagError::UnknownColour(colour) => write!(f, "Unknown colour {}", colour),
+            BagError::Cycle(path) => {
+                write!(f, "Bags contain themselves: {}", path.join(" contains "))
+            }
+            BagError::Overflow(colour) => write!(f, "Too many bags inside {} to count", colour),
+        }
+    }
+}
+
+impl<'a> BagGraph<'a> {
+    fn new(bags: &Bags<'a>) -> Self {
+        let mut graph = BagGraph {
+            colours: Vec::new(),
+            ids: HashMap::new(),
+            contents: Vec::new(),
+            containers: Vec::new(),
+            inner_counts: OnceCell::new(),
+            ancestors: OnceCell::new(),
+        };
+        // Sort so that ids don't depend on HashMap ordering
+        let mut parents: Vec<&&str> = bags.keys().collect();
+        parents.sort_unstable();
+        for parent in parents {
+            let parent_id = graph.intern(parent);
+            let mut children: Vec<&(u32, &str)> = bags[parent].iter().collect();
+            children.sort_unstable_by_key(|(_, colour)| *colour);
+            for &(count, child) in children {
+                let child_id = graph.intern(child);
+                graph.contents[parent_id].push((count, child_id));
+                graph.containers[child_id].push(parent_id);
+            }
+        }
+        graph
+    }
+
+    fn intern(&mut self, colour: &'a str) -> BagId {
+        if let Some(&id) = self.ids.get(colour) {
+            return id;
+        }
+        let id = self.colours.len();
+        self.colours.push(colour);
+        self.ids.insert(colour, id);
+        self.contents.push(Vec::new());
+        self.containers.push(Vec::new());
+        id
+    }
+
+    fn id(&self, colour: &str) -> Result<BagId, BagError> {
+        self.ids
+            .get(colour)
+            .copied()
+            .ok_or_else(|| BagError::UnknownColour(colour.to_string()))
+    }
+
+    fn topological_order(&self) -> Result<Vec<BagId>, BagError> {
+        // Kahn's algorithm - every bag comes before all of the bags inside it
+        let mut num_containers: Vec<usize> = self.containers.iter().map(Vec::len).collect();
+        let mut ready: Vec<BagId> = (0..self.colours.len())
+            .filter(|&id| num_containers[id] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.colours.len());
+        while let Some(id) = ready.pop() {
+            order.push(id);
+            for &(_, child) in &self.contents[id] {
+                num_containers[child] -= 1;
+                if num_containers[child] == 0 {
+                    ready.push(child);
+                }
+            }
+        }
+        if order.len() < self.colours.len() {
+            // Anything left over is in, or inside, a cycle
+            let remaining: Vec<bool> = num_containers.iter().map(|&n| n > 0).collect();
+            let cycle = self.find_cycle(&remaining);
+            return Err(BagError::Cycle(
+                cycle
+                    .iter()
+                    .map(|&id| self.colours[id].to_string())
+                    .collect(),
+            ));
+        }
+        Ok(order)
+    }
+
+    fn find_cycle(&self, remaining: &[bool]) -> Vec<BagId> {
+        // Every remaining bag has a container which also remains, so walking
+        // outwards through containers must eventually revisit a bag.
+        let start = remaining.iter().position(|&r| r).unwrap();
+        let mut path = vec![start];
+        let mut positions = HashMap::new();
+        positions.insert(start, 0);
+        loop {
+            let current = *path.last().unwrap();
+            let next = *self.containers[current]
+                .iter()
+                .find(|&&parent| remaining[parent])
+                .unwrap();
+            if let Some(&i) = positions.get(&next) {
+                // Reverse so that each bag contains the next
+                let mut cycle = path.split_off(i);
+                cycle.push(next);
+                cycle.reverse();
+                return cycle;
+            }
+            positions.insert(next, path.len());
+            path.push(next);
+        }
+    }
+
+    fn inner_counts(&self) -> Result<&[Option<u64>], BagError> {
+        // The number of bags inside each bag, or None if there are too many to
+        // count. Working from the innermost bags outwards means that every
+        // bag's contents are known by the time it is reached.
+        self.inner_counts
+            .get_or_init(|| {
+                let mut counts: Vec<Option<u64>> = vec![Some(0); self.colours.len()];
+                for id in self.topological_order()?.into_iter().rev() {
+                    counts[id] =
+                        self.contents[id]
+                            .iter()
+                            .try_fold(0u64, |total, &(count, child)| {
+                                let with_child = counts[child]?.checked_add(1)?;
+                                total.checked_add(u64::from(count).checked_mul(with_child)?)
+                            });
+                }
+                Ok(counts)
+            })
+            .as_deref()
+            .map_err(Clone::clone)
+    }
+
+    fn ancestors(&self) -> Result<&[BitSet], BagError> {
+        // The set of bags which eventually contain each bag. Working from the
+        // outermost bags inwards means that every bag's containers are known by
+        // the time it is reached.
+        self.ancestors
+            .get_or_init(|| {
+                let mut ancestors = vec![BitSet::new(self.colours.len()); self.colours.len()];
+                for id in self.topological_order()? {
+                    for &parent in &self.containers[id] {
+                        let parent_ancestors = ancestors[parent].clone();
+                        ancestors[id].union_with(&parent_ancestors);
+                        ancestors[id].insert(parent);
+                    }
+                }
+                Ok(ancestors)
+            })
+            .as_deref()
+            .map_err(Clone::clone)
+    }
+
+    fn count_inside(&self, colour: &str) -> Result<u64, BagError> {
+        self.inner_counts()?[self.id(colour)?].ok_or_else(|| BagError::Overflow(colour.to_string()))
+    }
+
+    fn count_containers(&self, colour: &str) -> Result<usize, BagError> {
+        Ok(self.ancestors()?[self.id(colour)?].len())
+    }
+
+    fn reachable(&self, start: BagId, edges: impl Fn(BagId) -> Vec<BagId>) -> BitSet {
+        // Depth first search from the start bag. Unlike the memoised queries,
+        // this copes with cycles, so that bad rules can still be exported.
+        let mut seen = BitSet::new(self.colours.len());
+        seen.insert(start);
+        let mut stack = vec![start];
+        while let Some(id) = stack.pop() {
+            for next in edges(id) {
+                if !seen.contains(next) {
+                    seen.insert(next);
+                    stack.push(next);
+                }
+            }
+        }
+        seen
+    }
+
+    fn included(&self, target: Option<&str>, restriction: Restriction) -> Result<BitSet, BagError> {
+        // The bags to export
+        let target = match target {
+            Some(colour) => self.id(colour)?,
+            None => {
+                let mut all = BitSet::new(self.colours.len());
+                (0..self.colours.len()).for_each(|id| all.insert(id));
+                return Ok(all);
+            }
+        };
+        Ok(match restriction {
+            Restriction::Ancestors => self.reachable(target, |id| self.containers[id].clone()),
+            Restriction::Descendants => self.reachable(target, |id| {
+                self.contents[id].iter().map(|&(_, child)| child).collect()
+            }),
+            Restriction::All => {
+                let mut all = BitSet::new(self.colours.len());
+                (0..self.colours.len()).for_each(|id| all.insert(id));
+                all
+            }
+        })
+    }
+
+    fn sorted(&self, included: &BitSet) -> Vec<BagId> {
+        // Exported bags are in alphabetical order
+        let mut ids: Vec<BagId> = (0..self.colours.len())
+            .filter(|&id| included.contains(id))
+            .collect();
+        ids.sort_unstable_by_key(|&id| self.colours[id]);
+        ids
+    }
+
+    fn to_dot(&self, target: Option<&str>, restriction: Restriction) -> Result<String, BagError> {
+        // Graphviz DOT, with an edge from each bag to each of the bags inside
+        // it labelled with how many, and the target bag highlighted
+        let included = self.included(target, restriction)?;
+        let ids = self.sorted(&included);
+        let mut output = String::from("digraph bags {\n");
+        for &id in &ids {
+            let colour = self.colours[id];
+            if Some(colour) == target {
+                output.push_str(&format!(
+                    "    {} [style=filled, fillcolor=gold];\n",
+                    quote(colour)
+                ));
+            } else {
+                output.push_str(&format!("    {};\n", quote(colour)));
+            }
+        }
+        for &id in &ids {
+            for &(count, child) in &self.contents[id] {
+                if included.contains(child) {
+                    output.push_str(&format!(
+                        "    {} -> {} [label=\"{}\"];\n",
+                        quote(self.colours[id]),
+                        quote(self.colours[child]),
+                        count
+                    ));
+                }
+            }
+        }
+        output.push_str("}\n");
+        Ok(output)
+    }
+
+    fn to_json(&self, target: Option<&str>, restriction: Restriction) -> Result<String, BagError> {
+        // Each bag along with the bags directly inside it
+        let included = self.included(target, restriction)?;
+        let bags: Vec<String> = self
+            .sorted(&included)
+            .into_iter()
+            .map(|id| {
+                let contents: Vec<String> = self.contents[id]
+                    .iter()
+                    .filter(|&&(_, child)| included.contains(child))
+                    .map(|&(count, child)| {
+                        format!(
+                            "{{\"count\":{},\"colour\":{}}}",
+                            count,
+                            quote(self.colours[child])
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{{\"colour\":{},\"contains\":[{}]}}",
+                    quote(self.colours[id]),
+                    contents.join(",")
+                )
+            })
+            .collect();
+        Ok(format!(
+            "{{\"target\":{},\"bags\":[{}]}}",
+            target.map_or("null".to_string(), quote),
+            bags.join(",")
+        ))
+    }
+}
+
+fn quote(colour: &str) -> String {
+    // A double quoted string, valid in both DOT and JSON
+    format!("\"{}\"", colour.replace('\\', "\\\\").replace('"', "\\\""))
 }
 
-fn recursive_count(bags: &Bags, key: &str) -> u32 {
-    let mut count = 0;
-    if let Some(set) = bags.get(key) {
-        for (inner_bag_count, colour) in set.iter() {
-            count += inner_bag_count * recursive_count(bags, &colour);
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Restriction {
+    All,
+    // Only the target bag and the bags which contain it
+    Ancestors,
+    // Only the target bag and the bags inside it
+    Descendants,
+}
+
+impl FromStr for Restriction {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "all" => Ok(Restriction::All),
+            "ancestors" => Ok(Restriction::Ancestors),
+            "descendants" => Ok(Restriction::Descendants),
+            _ => Err(format!("Unknown restriction {}", input)),
         }
     }
-    count + 1
 }
 
-fn recursive_get_all_parents<'a>(parent_bags: &ParentBags<'a>, child: &str) -> HashSet<&'a str> {
-    match parent_bags.get(child) {
-        None => HashSet::new(),
-        Some(parents) => parents
+#[derive(Debug, Clone)]
+struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    fn new(size: usize) -> Self {
+        BitSet {
+            words: vec![0; size.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.words[index / 64] |= 1 << (index % 64);
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.words[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn union_with(&mut self, other: &BitSet) {
+        for (word, other_word) in self.words.iter_mut().zip(&other.words) {
+            *word |= other_word;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.words
             .iter()
-            .map(|p| recursive_get_all_parents(parent_bags, p))
-            .fold(parents.clone(), |a, b| a.union(&b).cloned().collect()),
+            .map(|word| word.count_ones() as usize)
+            .sum()
     }
 }
 
-fn part_one(parent_bags: &ParentBags) -> usize {
-    recursive_get_all_parents(parent_bags, "shiny gold").len()
+fn part_one(graph: &BagGraph) -> Result<usize, BagError> {
+    graph.count_containers("shiny gold")
 }
 
-fn part_two(bags: &Bags) -> u32 {
-    recursive_count(bags, "shiny gold") - 1
+fn part_two(graph: &BagGraph) -> Result<u64, BagError> {
+    graph.count_inside("shiny gold")
 }
 
 #[test]
 fn test_parsing() {
     let input = "light red bags contain 1 bright white bag, 2 muted yellow bags.
 dotted black bags contain no other bags.";
-    let (bags, parent_bags) = parse_input(&input);
+    let bags = parse_input(input).unwrap();
     let mut expected: Bags = HashMap::new();
     let mut expected_lr = HashSet::new();
     expected_lr.insert((1u32, "bright white"));
     expected_lr.insert((2u32, "muted yellow"));
-    expected.insert(&"light red", expected_lr);
-    expected.insert(&"dotted black", HashSet::new());
-
-    let mut expected_parent_bags = HashMap::new();
-    let mut bw_parents = HashSet::new();
-    bw_parents.insert("light red");
-    expected_parent_bags.insert("bright white", bw_parents.clone());
-    expected_parent_bags.insert("muted yellow", bw_parents);
+    expected.insert("light red", expected_lr);
+    expected.insert("dotted black", HashSet::new());
     assert_eq!(bags, expected);
-    assert_eq!(parent_bags, expected_parent_bags);
+
+    // The graph works out which bags contain which
+    let graph = BagGraph::new(&bags);
+    for child in ["bright white", "muted yellow"] {
+        assert_eq!(
+            graph.containers[graph.ids[child]],
+            vec![graph.ids["light red"]]
+        );
+    }
+    assert!(graph.containers[graph.ids["light red"]].is_empty());
 }
 
 #[test]
@@ -106,7 +536,202 @@ dark olive bags contain 3 faded blue bags, 4 dotted black bags.
 vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags.";
-    let (bags, parent_bags) = parse_input(&input);
-    assert_eq!(part_one(&parent_bags), 4);
-    assert_eq!(part_two(&bags), 32);
+    let bags = parse_input(input).unwrap();
+    let graph = BagGraph::new(&bags);
+    assert_eq!(part_one(&graph), Ok(4));
+    assert_eq!(part_two(&graph), Ok(32));
+}
+
+#[test]
+fn test_bag_graph() {
+    let input = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+    let bags = parse_input(input).unwrap();
+    let graph = BagGraph::new(&bags);
+    assert_eq!(graph.colours.len(), 9);
+    let order = graph.topological_order().unwrap();
+    let position = |colour| order.iter().position(|&id| id == graph.id(colour).unwrap());
+    assert!(position("light red") < position("bright white"));
+    assert!(position("bright white") < position("shiny gold"));
+    assert!(position("shiny gold") < position("faded blue"));
+
+    assert_eq!(graph.count_containers("faded blue"), Ok(7));
+    assert_eq!(graph.count_containers("light red"), Ok(0));
+    assert_eq!(graph.count_inside("dark olive"), Ok(7));
+    assert_eq!(graph.count_inside("light red"), Ok(186));
+    assert_eq!(graph.count_inside("faded blue"), Ok(0));
+    assert_eq!(
+        graph.count_inside("plaid magenta"),
+        Err(BagError::UnknownColour("plaid magenta".to_string()))
+    );
+}
+
+#[test]
+fn test_bad_rules() {
+    let input = "light red bags contain 1 bright white bag.
+bright white bags contain 2 muted yellow bags, 1 faded blue bag.
+muted yellow bags contain 3 light red bags.
+faded blue bags contain no other bags.";
+    let bags = parse_input(input).unwrap();
+    let graph = BagGraph::new(&bags);
+    let cycle = BagError::Cycle(
+        ["bright white", "muted yellow", "light red", "bright white"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+    );
+    assert_eq!(part_two(&graph), Err(cycle.clone()));
+    assert_eq!(graph.count_containers("faded blue"), Err(cycle.clone()));
+    assert_eq!(
+        cycle.to_string(),
+        "Bags contain themselves: bright white contains muted yellow contains light red contains bright white"
+    );
+
+    let bags = parse_input("shiny gold bags contain 1 shiny gold bag.").unwrap();
+    let graph = BagGraph::new(&bags);
+    assert_eq!(
+        part_one(&graph),
+        Err(BagError::Cycle(vec!["shiny gold".to_string(); 2]))
+    );
+
+    // Each level multiplies the number of bags by 1000, so 7 levels overflow
+    let mut rules = String::new();
+    for level in 0..8 {
+        rules.push_str(&format!(
+            "level{} bags contain 1000 level{} bags.\n",
+            level,
+            level + 1
+        ));
+    }
+    rules.push_str("level8 bags contain no other bags.");
+    let bags = parse_input(&rules).unwrap();
+    let graph = BagGraph::new(&bags);
+    assert_eq!(graph.count_inside("level2"), Ok(1_001_001_001_001_001_000));
+    assert_eq!(
+        graph.count_inside("level1"),
+        Err(BagError::Overflow("level1".to_string()))
+    );
+    assert_eq!(
+        graph.count_inside("level0"),
+        Err(BagError::Overflow("level0".to_string()))
+    );
+    assert_eq!(graph.count_containers("level8"), Ok(8));
+}
+
+#[test]
+fn test_export() {
+    let input = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag.
+dark olive bags contain no other bags.
+faded blue bags contain no other bags.";
+    let bags = parse_input(input).unwrap();
+    let graph = BagGraph::new(&bags);
+    assert_eq!(
+        graph
+            .to_dot(Some("shiny gold"), Restriction::Descendants)
+            .unwrap(),
+        "digraph bags {
+    \"dark olive\";
+    \"shiny gold\" [style=filled, fillcolor=gold];
+    \"shiny gold\" -> \"dark olive\" [label=\"1\"];
+}
+"
+    );
+    let dot = graph
+        .to_dot(Some("shiny gold"), Restriction::Ancestors)
+        .unwrap();
+    assert!(dot.contains("\"muted yellow\" -> \"shiny gold\" [label=\"2\"];"));
+    assert!(!dot.contains("faded blue"));
+    assert!(!dot.contains("dark olive"));
+    assert_eq!(
+        graph
+            .to_dot(None, Restriction::All)
+            .unwrap()
+            .matches("->")
+            .count(),
+        6
+    );
+
+    assert_eq!(
+        graph
+            .to_json(Some("muted yellow"), Restriction::Descendants)
+            .unwrap(),
+        "{\"target\":\"muted yellow\",\"bags\":[\
+{\"colour\":\"dark olive\",\"contains\":[]},\
+{\"colour\":\"faded blue\",\"contains\":[]},\
+{\"colour\":\"muted yellow\",\"contains\":[\
+{\"count\":9,\"colour\":\"faded blue\"},{\"count\":2,\"colour\":\"shiny gold\"}]},\
+{\"colour\":\"shiny gold\",\"contains\":[{\"count\":1,\"colour\":\"dark olive\"}]}]}"
+    );
+    assert!(graph
+        .to_json(None, Restriction::All)
+        .unwrap()
+        .starts_with("{\"target\":null,"));
+    assert_eq!(
+        graph.to_dot(Some("plaid magenta"), Restriction::All),
+        Err(BagError::UnknownColour("plaid magenta".to_string()))
+    );
+
+    // Cycles can still be exported
+    let bags = parse_input("shiny gold bags contain 1 shiny gold bag.").unwrap();
+    let graph = BagGraph::new(&bags);
+    assert!(graph
+        .to_dot(Some("shiny gold"), Restriction::Ancestors)
+        .unwrap()
+        .contains("\"shiny gold\" -> \"shiny gold\" [label=\"1\"];"));
+}
+
+#[test]
+fn test_tolerant_parsing() {
+    let input = "very dark shimmering red bags contain 1 gold bag, 2 pale muted yellow bags
+
+gold bag contains no other bags.
+gold bags contain no other bag
+pale muted yellow bags contain 3 gold bags.";
+    // "contains" isn't allowed
+    assert_eq!(
+        parse_input(input).unwrap_err(),
+        "Line 3: Expected \"contain\": gold bag contains no other bags."
+    );
+    let input = input.replace("contains", "contain");
+    assert_eq!(
+        parse_input(&input).unwrap_err(),
+        "Line 4: Duplicate rule for gold: gold bags contain no other bag"
+    );
+    let input = input.replace("gold bags contain no other bag\n", "");
+    let bags = parse_input(&input).unwrap();
+    let graph = BagGraph::new(&bags);
+    assert_eq!(graph.count_inside("very dark shimmering red"), Ok(9));
+    assert_eq!(graph.count_containers("gold"), Ok(2));
+
+    for (rule, error) in [
+        (
+            "red bags contain two blue bags.",
+            "Bad number of bags \"two\"",
+        ),
+        (
+            "red bags contain 2 blue.",
+            "Expected \"bag\" or \"bags\" after \"blue\"",
+        ),
+        (
+            "red bags contain 2bags.",
+            "Expected a number of bags in \"2bags\"",
+        ),
+        ("bags contain no other bags.", "Missing colour in \"bags\""),
+        ("red bags contain 2  bags.", "Missing colour in \"bags\""),
+    ] {
+        assert_eq!(
+            parse_input(rule).unwrap_err(),
+            format!("Line 1: {}: {}", error, rule)
+        );
+    }
 }
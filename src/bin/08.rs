@@ -1,78 +1,390 @@
-use std::collections::HashSet;
-use std::num::ParseIntError;
-use std::str::FromStr;
+use aoc2020::vm::{self, Instruction, InstructionSet, Operand, Program, ProgramHalt};
+use std::collections::{BTreeSet, HashSet};
+use std::io::{self, BufRead, Write};
 use std::time::Instant;
 
-#[derive(Debug, Clone)]
-enum Instruction {
-    Jump(isize),
-    Acc(i32),
-    Noop(isize),
+#[derive(Debug, Clone, Copy)]
+enum Watch {
+    // Stop whenever the accumulator changes
+    Change,
+    // Stop when the accumulator takes this value
+    Value(i64),
 }
 
-enum ProgramHalt {
-    NormalExit(i32),
-    Loop(i32),
+// Why the debugger stopped running the program
+enum Stop {
+    Stepped,
+    Breakpoint,
+    Watchpoint(i64),
+    Loop,
+    Halt(ProgramHalt),
 }
 
-#[derive(Debug, Clone)]
-struct Program {
-    instructions: Vec<Instruction>,
-    accumulator: i32,
-    program_counter: usize,
+struct Debugger {
+    initial: Program,
+    program: Program,
+    breakpoints: BTreeSet<usize>,
+    watch: Option<Watch>,
+    // (program_counter, registers) before each executed instruction, so that
+    // we can step backwards
+    history: Vec<(usize, Vec<i64>)>,
 }
 
-impl FromStr for Program {
-    type Err = ParseIntError;
-    fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let mut instructions = Vec::new();
-        for line in input.lines() {
-            let mut parts = line.split(' ');
-            match parts.next().unwrap() {
-                "jmp" => instructions.push(Instruction::Jump(parts.next().unwrap().parse()?)),
-                "acc" => instructions.push(Instruction::Acc(parts.next().unwrap().parse()?)),
-                "nop" => instructions.push(Instruction::Noop(parts.next().unwrap().parse()?)),
-                _ => panic!("Got unexpected instruction: {}!", line),
-            }
+impl Debugger {
+    fn new(program: &Program) -> Self {
+        Debugger {
+            initial: program.clone(),
+            program: program.clone(),
+            breakpoints: BTreeSet::new(),
+            watch: None,
+            history: Vec::new(),
+        }
+    }
+
+    fn describe(&self, pc: usize) -> String {
+        match self.program.instructions.get(pc) {
+            Some(instruction) => format!("{}: {}", pc, self.program.format(instruction)),
+            None if pc == self.program.instructions.len() => format!("{}: <end>", pc),
+            None => format!("{}: <out of bounds>", pc),
+        }
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "{} (acc {})",
+            self.describe(self.program.program_counter),
+            self.program.accumulator()
+        )
+    }
+
+    // Execute one instruction, or explain why we can't
+    fn step(&mut self) -> Stop {
+        let pc = self.program.program_counter;
+        let registers = self.program.registers.clone();
+        if let Some(halt) = self.program.step() {
+            return Stop::Halt(halt);
+        }
+        let before = registers[0];
+        self.history.push((pc, registers));
+        let after = self.program.accumulator();
+        match self.watch {
+            Some(Watch::Change) if after != before => Stop::Watchpoint(before),
+            Some(Watch::Value(val)) if after == val && before != val => Stop::Watchpoint(before),
+            _ => Stop::Stepped,
         }
-        Ok(Program {
-            instructions,
-            accumulator: 0,
-            program_counter: 0,
-        })
     }
-}
 
-impl Program {
-    fn run(&mut self) -> ProgramHalt {
+    // Keep stepping until something interesting happens. Like `run`, we stop
+    // before executing any instruction a second time.
+    fn cont(&mut self) -> Stop {
         let mut seen_positions = HashSet::new();
-        while !seen_positions.contains(&self.program_counter) {
-            if self.program_counter == self.instructions.len() {
-                return ProgramHalt::NormalExit(self.accumulator);
+        loop {
+            seen_positions.insert(self.program.program_counter);
+            match self.step() {
+                Stop::Stepped => {}
+                stop => return stop,
+            }
+            let pc = self.program.program_counter;
+            if self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint;
+            }
+            if seen_positions.contains(&pc) {
+                return Stop::Loop;
+            }
+        }
+    }
+
+    fn report(&self, stop: Stop) -> String {
+        let acc = self.program.accumulator();
+        match stop {
+            Stop::Stepped => self.status(),
+            Stop::Breakpoint => format!("Breakpoint at {}", self.status()),
+            Stop::Watchpoint(before) => {
+                format!("Watchpoint: acc {} -> {} at {}", before, acc, self.status())
+            }
+            Stop::Loop => format!("Loop detected at {}", self.status()),
+            Stop::Halt(ProgramHalt::NormalExit) => format!("Program exited with acc {}", acc),
+            Stop::Halt(ProgramHalt::OutOfBounds(target)) => {
+                format!("Jump to {} out of bounds at {}", target, self.status())
+            }
+            Stop::Halt(halt) => format!("{:?} at {}", halt, self.status()),
+        }
+    }
+
+    // Run a single command, returning the response, or None if we should quit
+    fn execute(&mut self, command: &str) -> Option<String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let number = |i: usize| -> Result<usize, String> {
+            match words.get(i) {
+                Some(word) => word
+                    .parse()
+                    .map_err(|_| format!("Expected a number, got {:?}", word)),
+                None => Ok(1),
             }
-            seen_positions.insert(self.program_counter);
-            match self.instructions[self.program_counter] {
-                Instruction::Jump(jump_len) => {
-                    // Increment the program counter by the jump_len
-                    self.program_counter = (self.program_counter as isize + jump_len) as usize;
+        };
+        let response = match words.first().copied() {
+            None => return Some(String::new()),
+            Some("quit") | Some("q") => return None,
+            Some("step") | Some("s") => number(1).map(|n| {
+                let mut stop = Stop::Stepped;
+                for _ in 0..n {
+                    stop = self.step();
+                    if !matches!(stop, Stop::Stepped) {
+                        break;
+                    }
+                }
+                self.report(stop)
+            }),
+            Some("continue") | Some("c") => {
+                let stop = self.cont();
+                Ok(self.report(stop))
+            }
+            Some("back") | Some("b") => number(1).map(|n| {
+                let n = n.min(self.history.len());
+                if n > 0 {
+                    let (pc, registers) =
+                        self.history.drain(self.history.len() - n..).next().unwrap();
+                    self.program.program_counter = pc;
+                    self.program.registers = registers;
+                    self.program.executed -= n;
                 }
-                Instruction::Acc(acc_size) => {
-                    // Add to the accumulator and increment the program counter
-                    // by 1
-                    self.accumulator += acc_size;
-                    self.program_counter += 1;
+                format!("Rewound {} step(s) to {}", n, self.status())
+            }),
+            Some("print") | Some("p") => Ok(self.status()),
+            Some("inspect") | Some("i") => match words.get(1) {
+                Some(_) => number(1).map(|pc| self.describe(pc)),
+                None => Ok(self.describe(self.program.program_counter)),
+            },
+            Some("break") => match words.get(1) {
+                Some(_) => number(1).map(|pc| {
+                    self.breakpoints.insert(pc);
+                    format!("Breakpoint set at {}", self.describe(pc))
+                }),
+                None => Ok(format!("Breakpoints: {:?}", self.breakpoints)),
+            },
+            Some("delete") => number(1).map(|pc| match self.breakpoints.remove(&pc) {
+                true => format!("Breakpoint at {} deleted", pc),
+                false => format!("No breakpoint at {}", pc),
+            }),
+            Some("watch") => match words.get(1) {
+                Some(word) => word
+                    .parse()
+                    .map_err(|_| format!("Expected a value, got {:?}", word))
+                    .map(|val| {
+                        self.watch = Some(Watch::Value(val));
+                        format!("Watching for acc == {}", val)
+                    }),
+                None => {
+                    self.watch = Some(Watch::Change);
+                    Ok("Watching for changes to acc".to_string())
                 }
-                Instruction::Noop(_) => self.program_counter += 1,
+            },
+            Some("unwatch") => {
+                self.watch = None;
+                Ok("Watchpoint removed".to_string())
+            }
+            Some("patch") => number(1).and_then(|pc| {
+                if pc >= self.program.instructions.len() {
+                    return Err(format!("No instruction at {}", pc));
+                }
+                let instruction = self
+                    .program
+                    .instruction_set()
+                    .parse_instruction(&words[2..].join(" "))?;
+                self.program.instructions[pc] = instruction;
+                Ok(format!("Patched {}", self.describe(pc)))
+            }),
+            Some("reset") => {
+                self.program = self.initial.clone();
+                self.history.clear();
+                Ok(format!("Reset to {}", self.status()))
+            }
+            Some(other) => Err(format!("Unknown command {:?}", other)),
+        };
+        Some(response.unwrap_or_else(|e| format!("Error: {}", e)))
+    }
+
+    fn run_session(&mut self, input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+        writeln!(output, "{}", self.status())?;
+        for line in input.lines() {
+            match self.execute(&line?) {
+                Some(response) => writeln!(output, "{}", response)?,
+                None => break,
             }
         }
-        ProgramHalt::Loop(self.accumulator)
+        Ok(())
     }
 }
 
+// A jmp/nop which, once flipped, makes the program terminate
+#[derive(Debug, PartialEq, Eq)]
+struct Repair {
+    index: usize,
+    instruction: Instruction,
+    accumulator: i64,
+}
+
+// Where a handheld instruction sends the program counter (None if it jumps
+// out of bounds) and how much it adds to the accumulator. Only literal
+// arguments can be analysed statically.
+fn effect(
+    pc: usize,
+    instruction: &Instruction,
+    len: usize,
+) -> Result<(Option<usize>, i64), String> {
+    let (offset, acc) = match (instruction.op.name, instruction.args()) {
+        ("jmp", [Operand::Value(offset)]) => (*offset, 0),
+        ("acc", [Operand::Value(val)]) => (1, *val),
+        ("nop", _) => (1, 0),
+        _ => return Err(format!("Can't analyse instruction {}", pc)),
+    };
+    let target = pc as i64 + offset;
+    Ok((
+        (0..=len as i64)
+            .contains(&target)
+            .then_some(target as usize),
+        acc,
+    ))
+}
+
+// Find every single jmp/nop flip that makes the program terminate, in linear
+// time. Each instruction has exactly one successor, so walking the control
+// flow graph backwards from the exit finds every instruction that terminates,
+// along with what it adds to the accumulator on the way out. A flip can then
+// only help if it's on the original path from the start, and sends us to one
+// of those instructions.
+fn repairs(program: &Program) -> Result<Vec<Repair>, String> {
+    let len = program.instructions.len();
+    let effects = program
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(pc, instruction)| effect(pc, instruction, len))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Index `len` is the exit
+    let mut predecessors = vec![Vec::new(); len + 1];
+    for (pc, (next, _)) in effects.iter().enumerate() {
+        if let Some(next) = next {
+            predecessors[*next].push(pc);
+        }
+    }
+    // Whether each instruction reaches the exit, and if so what it adds to the
+    // accumulator on the way (None if that overflows)
+    let mut reaches_exit = vec![false; len + 1];
+    let mut to_exit = vec![None; len + 1];
+    reaches_exit[len] = true;
+    to_exit[len] = Some(0);
+    let mut stack = vec![len];
+    while let Some(next) = stack.pop() {
+        for &pc in &predecessors[next] {
+            reaches_exit[pc] = true;
+            to_exit[pc] = to_exit[next].and_then(|acc: i64| acc.checked_add(effects[pc].1));
+            stack.push(pc);
+        }
+    }
+    let overflow = |pc| format!("Accumulator overflows after instruction {}", pc);
+    if reaches_exit[0] {
+        return Err("Program already terminates".to_string());
+    }
+
+    let mut candidates = Vec::new();
+    let mut seen_positions = vec![false; len];
+    let mut pc = 0;
+    let mut accumulator: i64 = 0;
+    while !seen_positions[pc] {
+        seen_positions[pc] = true;
+        let instruction = &program.instructions[pc];
+        let flipped = match instruction.op.name {
+            "jmp" => Some(Instruction::new(vm::NOP, instruction.args())),
+            "nop" => Some(Instruction::new(vm::JMP, instruction.args())),
+            _ => None,
+        };
+        if let Some(flipped) = flipped {
+            if let (Some(next), _) = effect(pc, &flipped, len)? {
+                if reaches_exit[next] {
+                    let total = to_exit[next].and_then(|rest| accumulator.checked_add(rest));
+                    candidates.push(Repair {
+                        index: pc,
+                        instruction: flipped,
+                        accumulator: total.ok_or_else(|| overflow(pc))?,
+                    });
+                }
+            }
+        }
+        accumulator = accumulator
+            .checked_add(effects[pc].1)
+            .ok_or_else(|| overflow(pc))?;
+        match effects[pc].0 {
+            Some(next) => pc = next,
+            // The program crashes rather than loops
+            None => break,
+        }
+    }
+    Ok(candidates)
+}
+
 fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
     let input = std::fs::read_to_string("input/08")?;
     let program = parse_input(&input);
+    // e.g. `cargo run --bin 08 -- debug`, then commands such as
+    // `step 3`, `break 10`, `watch`, `continue`, `back`, `patch 4 nop +1`
+    if std::env::args().nth(1).as_deref() == Some("debug") {
+        let stdin = io::stdin();
+        return Debugger::new(&program).run_session(stdin.lock(), &mut io::stdout());
+    }
+    // e.g. `cargo run --bin 08 -- trace json trace.json`
+    if std::env::args().nth(1).as_deref() == Some("trace") {
+        let args: Vec<String> = std::env::args().collect();
+        let mut traced = program.clone().with_trace();
+        traced.run_until_repeat();
+        let trace = traced.trace.as_ref().unwrap();
+        let report = match args.get(2).map(String::as_str) {
+            Some("json") => trace.to_json(&traced),
+            _ => trace.to_text(&traced),
+        };
+        match args.get(3) {
+            Some(path) => std::fs::write(path, report)?,
+            None => println!("{}", report.trim_end()),
+        }
+        return Ok(());
+    }
+    // e.g. `cargo run --bin 08 -- save repaired.bin bin` writes the repaired
+    // program, as labelled assembly unless `bin` is given
+    if std::env::args().nth(1).as_deref() == Some("save") {
+        let args: Vec<String> = std::env::args().collect();
+        let repair = repairs(&program)
+            .and_then(|repairs| {
+                repairs
+                    .into_iter()
+                    .next()
+                    .ok_or("No repair found".to_string())
+            })
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut repaired = program.clone();
+        repaired.instructions[repair.index] = repair.instruction;
+        let path = args.get(2).map_or("repaired", String::as_str);
+        match args.get(3).map(String::as_str) {
+            Some("bin") => std::fs::write(path, repaired.encode())?,
+            _ => std::fs::write(path, repaired.disassemble())?,
+        }
+        return Ok(());
+    }
+    // e.g. `cargo run --bin 08 -- repairs`
+    if std::env::args().nth(1).as_deref() == Some("repairs") {
+        for repair in
+            repairs(&program).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        {
+            println!(
+                "Change {} to `{}`: acc {}",
+                repair.index,
+                program.format(&repair.instruction),
+                repair.accumulator
+            );
+        }
+        return Ok(());
+    }
     println!("Part 1: {}", part_one(&program));
     println!("Part 2: {}", part_two(&program));
     println!("Time: {}µs", now.elapsed().as_micros());
@@ -80,35 +392,23 @@ fn main() -> Result<(), std::io::Error> {
 }
 
 fn parse_input(input: &str) -> Program {
-    Program::from_str(&input).unwrap()
+    InstructionSet::handheld().parse(input).unwrap()
 }
 
-fn part_one(program: &Program) -> i32 {
-    match program.clone().run() {
-        ProgramHalt::Loop(ans) => ans,
+fn part_one(program: &Program) -> i64 {
+    let mut program = program.clone();
+    match program.run_until_repeat() {
+        ProgramHalt::Loop => program.accumulator(),
         _ => panic!("Expected Loop"),
     }
 }
 
-fn part_two(program: &Program) -> i32 {
-    // Loop through all of the instructions.
-    // Each time it's a Jump or a Noop, switch them and try running.
-    // If the program exits normally, return the accumulated value.
-    // Otherwise, continue
-    for (i, inst) in program.instructions.iter().enumerate() {
-        let modified = match inst {
-            Instruction::Acc(_) => continue,
-            Instruction::Jump(val) => Instruction::Noop(*val),
-            Instruction::Noop(val) => Instruction::Jump(*val),
-        };
-        let mut clone = program.clone();
-        clone.instructions[i] = modified;
-        match clone.run() {
-            ProgramHalt::Loop(_) => continue,
-            ProgramHalt::NormalExit(ans) => return ans,
-        }
-    }
-    unreachable!();
+fn part_two(program: &Program) -> i64 {
+    repairs(program)
+        .unwrap()
+        .first()
+        .expect("No repair found")
+        .accumulator
 }
 
 #[test]
@@ -122,7 +422,106 @@ acc -99
 acc +1
 jmp -4
 acc +6";
-    let program = parse_input(&input);
+    let program = parse_input(input);
     assert_eq!(part_one(&program), 5);
     assert_eq!(part_two(&program), 8);
 }
+
+#[test]
+fn test_debugger() {
+    let input = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+    let script = "step 2
+inspect
+break 4
+continue
+watch
+continue
+back 2
+watch 7
+patch 7 nop -4
+continue
+unwatch
+continue
+bogus
+quit
+step";
+    let mut output = Vec::new();
+    Debugger::new(&parse_input(input))
+        .run_session(script.as_bytes(), &mut output)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "0: nop +0 (acc 0)
+2: jmp +4 (acc 1)
+2: jmp +4
+Breakpoint set at 4: jmp -3
+Breakpoint at 4: jmp -3 (acc 5)
+Watching for changes to acc
+Watchpoint: acc 5 -> 6 at 2: jmp +4 (acc 6)
+Rewound 2 step(s) to 4: jmp -3 (acc 5)
+Watching for acc == 7
+Patched 7: nop -4
+Watchpoint: acc 6 -> 7 at 7: nop -4 (acc 7)
+Watchpoint removed
+Program exited with acc 13
+Error: Unknown command \"bogus\"
+"
+    );
+}
+
+#[test]
+fn test_repairs() {
+    let example = parse_input(
+        "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6",
+    );
+    assert_eq!(
+        repairs(&example).unwrap(),
+        vec![Repair {
+            index: 7,
+            instruction: Instruction::new(vm::NOP, &[Operand::Value(-4)]),
+            accumulator: 8,
+        }]
+    );
+
+    // Both the nop at 2 and the jmp at 4 can be flipped; the jmp at 1 can't
+    let program = parse_input("jmp +2\njmp -1\nnop +3\nacc +3\njmp -3");
+    let found: Vec<_> = repairs(&program)
+        .unwrap()
+        .iter()
+        .map(|repair| (repair.index, repair.accumulator))
+        .collect();
+    assert_eq!(found, vec![(2, 0), (4, 3)]);
+    for repair in repairs(&program).unwrap() {
+        let mut patched = program.clone();
+        patched.instructions[repair.index] = repair.instruction;
+        assert_eq!(patched.run_until_repeat(), ProgramHalt::NormalExit);
+        assert_eq!(patched.accumulator(), repair.accumulator);
+    }
+
+    assert!(repairs(&parse_input("acc +1\njmp +1")).is_err());
+    // The VM stops with ProgramHalt::Overflow here, so we can't repair it
+    assert_eq!(
+        repairs(&parse_input("acc +9223372036854775807\nacc +1\njmp -2")),
+        Err("Accumulator overflows after instruction 1".to_string())
+    );
+    assert!(repairs(&parse_input(
+        "nop +3\nacc +9223372036854775807\njmp -2\nacc +1"
+    ))
+    .is_err());
+}
@@ -1,14 +1,148 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::ops::Range;
 use std::time::Instant;
 
 const PREAMBLE_LEN: usize = 25;
 
+// A number which isn't the sum of two different numbers in the window before it
+#[derive(Debug, PartialEq, Eq)]
+struct Invalid {
+    index: usize,
+    value: u64,
+}
+
+struct Validator<I> {
+    numbers: I,
+    preamble_len: usize,
+    // The last `preamble_len` numbers in order, and how many times each value
+    // appears among them, so that removing one copy of a duplicate leaves the
+    // others in place
+    window: VecDeque<u64>,
+    counts: HashMap<u64, usize>,
+    index: usize,
+}
+
+impl<I> Validator<I> {
+    fn is_valid(&self, n: u64) -> bool {
+        self.counts
+            .keys()
+            .any(|&x| n > x && n - x != x && self.counts.contains_key(&(n - x)))
+    }
+}
+
+impl<I: Iterator<Item = io::Result<u64>>> Iterator for Validator<I> {
+    type Item = io::Result<Invalid>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let n = match self.numbers.next()? {
+                Ok(n) => n,
+                Err(e) => return Some(Err(e)),
+            };
+            let index = self.index;
+            self.index += 1;
+            // Numbers in the preamble are always valid
+            let invalid = self.window.len() == self.preamble_len && !self.is_valid(n);
+            self.window.push_back(n);
+            *self.counts.entry(n).or_insert(0) += 1;
+            if self.window.len() > self.preamble_len {
+                let old = self.window.pop_front().unwrap();
+                let count = self.counts.get_mut(&old).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&old);
+                }
+            }
+            if invalid {
+                return Some(Ok(Invalid { index, value: n }));
+            }
+        }
+    }
+}
+
+// Every invalid number in the stream, in order
+fn validate<I>(numbers: I, preamble_len: usize) -> Validator<I::IntoIter>
+where
+    I: IntoIterator<Item = io::Result<u64>>,
+{
+    Validator {
+        numbers: numbers.into_iter(),
+        preamble_len,
+        window: VecDeque::new(),
+        counts: HashMap::new(),
+        index: 0,
+    }
+}
+
+// Read one number per line, without holding the whole input in memory
+fn read_numbers<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<u64>> {
+    reader.lines().enumerate().map(|(i, line)| {
+        line?.trim().parse().map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", i + 1, e))
+        })
+    })
+}
+
 fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
+    let mut args: Vec<String> = std::env::args().collect();
+    // e.g. `--preamble 5` to use a shorter preamble
+    let mut preamble_len = PREAMBLE_LEN;
+    if let Some(i) = args.iter().position(|a| a == "--preamble") {
+        preamble_len = args
+            .get(i + 1)
+            .and_then(|a| a.parse().ok())
+            .expect("Expected a preamble length");
+        args.drain(i..(i + 2));
+    }
+    if args.get(1).map(String::as_str) == Some("stream") {
+        // e.g. `cargo run --bin 09 -- stream <file>` to list every invalid
+        // number in a file of any size
+        let path = args.get(2).map_or("input/09", String::as_str);
+        let numbers = read_numbers(BufReader::new(File::open(path)?));
+        for invalid in validate(numbers, preamble_len) {
+            let invalid = invalid?;
+            println!("{}: {}", invalid.index, invalid.value);
+        }
+        return Ok(());
+    }
     let input = std::fs::read_to_string("input/09")?;
     let numbers = parse_input(&input);
-    println!("Part 1: {}", part_one(&numbers, PREAMBLE_LEN));
-    println!("Part 2: {}", part_two(&numbers, PREAMBLE_LEN));
+    if args.get(1).map(String::as_str) == Some("ranges") {
+        // e.g. `cargo run --bin 09 -- ranges 127 150` to find every range
+        // summing to each target
+        let prefix_sums = PrefixSums::new(&numbers);
+        for target in args[2..]
+            .iter()
+            .map(|a| a.parse().expect("Expected a target"))
+        {
+            let ranges = prefix_sums.ranges_summing_to(target);
+            if ranges.is_empty() {
+                println!("{}: none found", target);
+            }
+            for range in ranges {
+                println!(
+                    "{}: positions {} to {} ({} numbers)",
+                    target,
+                    range.start,
+                    range.end - 1,
+                    range.len()
+                );
+            }
+        }
+        return Ok(());
+    }
+    let no_invalid = || io::Error::new(io::ErrorKind::InvalidData, "No invalid number found");
+    println!(
+        "Part 1: {}",
+        part_one(&numbers, preamble_len).ok_or_else(no_invalid)?
+    );
+    println!(
+        "Part 2: {}",
+        part_two(&numbers, preamble_len)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    );
     println!("Time: {}µs", now.elapsed().as_micros());
     Ok(())
 }
@@ -17,51 +151,63 @@ fn parse_input(input: &str) -> Vec<u64> {
     input.lines().map(|l| l.parse().unwrap()).collect()
 }
 
-fn part_one(numbers: &[u64], preamble_len: usize) -> u64 {
-    let mut possible_summants: HashSet<&u64> = numbers.iter().take(preamble_len).collect();
-    for (i, n) in numbers.iter().skip(preamble_len).enumerate() {
-        match possible_summants
-            .iter()
-            .find(|&x| n > *x && possible_summants.contains(&(n - *x)))
-        {
-            None => return *n,
-            Some(_) => {
-                // Modify the possible summants set in preparation for the next
-                // iteration.
-                possible_summants.remove(&numbers[i]);
-                possible_summants.insert(&numbers[i + preamble_len]);
-            }
+fn part_one(numbers: &[u64], preamble_len: usize) -> Option<u64> {
+    validate(numbers.iter().map(|&n| Ok(n)), preamble_len)
+        .flatten()
+        .next()
+        .map(|invalid| invalid.value)
+}
+
+// Running totals, so that the sum of any range is a single subtraction and
+// the same data can be queried for many targets
+struct PrefixSums {
+    // sums[i] is the total of the first i numbers
+    sums: Vec<u128>,
+}
+
+impl PrefixSums {
+    fn new(numbers: &[u64]) -> Self {
+        let mut sums = vec![0];
+        for &n in numbers {
+            sums.push(sums[sums.len() - 1] + n as u128);
         }
+        PrefixSums { sums }
     }
-    unreachable!();
-}
 
-fn part_two(numbers: &[u64], preamble_len: usize) -> u64 {
-    // Because the numbers are all positive, we can iterate through with two
-    // indices, lower and upper, as follows:
-    //  * If the sum of numbers between lower and upper is too small, then
-    //     upper is guaranteed to be too low, so increase it.
-    //  * If the sum of numbers between lower and upper is too large, then
-    //     lower is guaranteed to be too low, so increase it.
-    let target = part_one(numbers, preamble_len);
-    let mut lower: usize = 0;
-    let mut upper: usize = 1;
-    let mut sum: u64 = numbers[lower] + numbers[upper];
-    while sum != target {
-        if sum < target {
-            // upper is too low
-            upper += 1;
-            sum += numbers[upper];
-        } else {
-            // lower is too low
-            sum -= numbers[lower];
-            lower += 1;
+    // Every range of at least two numbers which sums to the target, ordered by
+    // start then end
+    fn ranges_summing_to(&self, target: u64) -> Vec<Range<usize>> {
+        // All the numbers are non-negative, so the sums never decrease and
+        // the starts matching an end form a contiguous block which we can
+        // binary search for
+        let target = target as u128;
+        let mut ranges = Vec::new();
+        for end in 2..self.sums.len() {
+            let wanted = match self.sums[end].checked_sub(target) {
+                Some(wanted) => wanted,
+                None => continue,
+            };
+            let candidates = &self.sums[..end - 1];
+            let first = candidates.partition_point(|&sum| sum < wanted);
+            let last = candidates.partition_point(|&sum| sum <= wanted);
+            ranges.extend((first..last).map(|start| start..end));
         }
+        ranges.sort_by_key(|range| (range.start, range.end));
+        ranges
     }
-    // We're done - return the min + max of the interval
-    let min = numbers[lower..=upper].iter().min().unwrap();
-    let max = numbers[lower..=upper].iter().max().unwrap();
-    min + max
+}
+
+fn part_two(numbers: &[u64], preamble_len: usize) -> Result<u64, String> {
+    let target = part_one(numbers, preamble_len).ok_or("No invalid number found")?;
+    let ranges = PrefixSums::new(numbers).ranges_summing_to(target);
+    let range = ranges.first().ok_or(format!(
+        "No range of at least two numbers sums to {}",
+        target
+    ))?;
+    // Return the min + max of the interval
+    let min = numbers[range.clone()].iter().min().unwrap();
+    let max = numbers[range.clone()].iter().max().unwrap();
+    Ok(min + max)
 }
 
 #[test]
@@ -87,6 +233,46 @@ fn test_examples() {
 309
 576";
     let numbers = parse_input(input);
-    assert_eq!(part_one(&numbers, 5), 127);
-    assert_eq!(part_two(&numbers, 5), 62);
+    assert_eq!(part_one(&numbers, 5), Some(127));
+    assert_eq!(part_two(&numbers, 5), Ok(62));
+}
+
+#[test]
+fn test_streaming() {
+    // When the first 1 leaves the window the second is still there, so
+    // 5 = 1 + 4 is valid
+    let input = "1\n2\n1\n3\n4\n5\n100\n2";
+    let invalid: Vec<Invalid> = validate(read_numbers(input.as_bytes()), 3)
+        .collect::<io::Result<_>>()
+        .unwrap();
+    assert_eq!(
+        invalid,
+        vec![
+            Invalid {
+                index: 6,
+                value: 100
+            },
+            Invalid { index: 7, value: 2 },
+        ]
+    );
+    // The two numbers in the pair must differ
+    assert_eq!(part_one(&[1, 1, 2], 2), Some(2));
+    assert_eq!(part_one(&[1, 2, 3], 2), None);
+    assert!(validate(read_numbers("1\n2\nthree".as_bytes()), 2)
+        .next()
+        .unwrap()
+        .is_err());
+}
+
+#[test]
+fn test_ranges() {
+    let prefix_sums = PrefixSums::new(&[3, 0, 2, 1, 4, 5]);
+    // A single number doesn't count, but zeros give extra ranges
+    assert_eq!(prefix_sums.ranges_summing_to(3), vec![0..2, 1..4, 2..4]);
+    assert_eq!(prefix_sums.ranges_summing_to(9), vec![4..6]);
+    assert_eq!(prefix_sums.ranges_summing_to(100), vec![]);
+    assert_eq!(
+        part_two(&[1, 2, 4, 8, 16, 32, 64], 2),
+        Err("No range of at least two numbers sums to 4".to_string())
+    );
 }
@@ -1,7 +1,21 @@
-use std::collections::HashMap;
+use num_bigint::BigUint;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::time::Instant;
 
-fn num_paths(adapters: &[u32], index: usize, seen: &mut HashMap<usize, u64>) -> u64 {
+// The largest joltage difference an adapter can bridge, and how far above
+// the largest adapter the device is
+const TOLERANCE: u32 = 3;
+const DEVICE_OFFSET: u32 = 3;
+
+// The original recursive count, kept to check and benchmark the iterative
+// versions against. It recurses once per adapter, so needs a big stack for
+// long inputs.
+fn num_paths_recursive(
+    adapters: &[u32],
+    tolerance: u32,
+    index: usize,
+    seen: &mut HashMap<usize, BigUint>,
+) -> BigUint {
     // Recursively find the number of paths to the given index through the
     // given slice, as follows:
     //  * If the index is 0, there is one path to the index.
@@ -9,57 +23,349 @@ fn num_paths(adapters: &[u32], index: usize, seen: &mut HashMap<usize, u64>) ->
     //    the paths to all of the preceding indices which can reach the given
     //    index.
     // Cache the results for performance (Fibonacci-esque).
-    if seen.contains_key(&index) {
-        *seen.get(&index).unwrap()
+    if let Some(total) = seen.get(&index) {
+        total.clone()
     } else if index == 0 {
-        1
+        BigUint::from(1u32)
     } else {
-        let mut total = 0;
+        let mut total = BigUint::from(0u32);
         let mut back = 1;
-        while back <= index && adapters[index] - adapters[index - back] <= 3 {
-            total += num_paths(adapters, index - back, seen);
+        while back <= index && adapters[index] - adapters[index - back] <= tolerance {
+            total += num_paths_recursive(adapters, tolerance, index - back, seen);
             back += 1;
         }
-        seen.insert(index, total);
+        seen.insert(index, total.clone());
         total
     }
 }
 
+// The number of paths to the last adapter. Working up from the start, the
+// number of paths to each adapter is the sum over the adapters within reach
+// below it, so we only need a running total for each joltage within reach -
+// at most `tolerance + 1` of them, however many adapters share a joltage.
+fn count_arrangements(adapters: &[u32], tolerance: u32) -> BigUint {
+    let mut window: VecDeque<(u32, BigUint)> = VecDeque::new();
+    let mut count = BigUint::from(1u32);
+    let mut total = count.clone();
+    window.push_back((adapters[0], count.clone()));
+    for &joltage in &adapters[1..] {
+        while let Some((lowest, _)) = window.front() {
+            if joltage - lowest <= tolerance {
+                break;
+            }
+            total -= window.pop_front().unwrap().1;
+        }
+        count = total.clone();
+        total += &count;
+        match window.back_mut() {
+            Some((last, paths)) if *last == joltage => *paths += &count,
+            _ => window.push_back((joltage, count.clone())),
+        }
+    }
+    count
+}
+
+// The number of paths to every adapter, in the same way
+fn path_counts(adapters: &[u32], tolerance: u32) -> Vec<BigUint> {
+    let mut counts = vec![BigUint::from(1u32)];
+    let mut total = BigUint::from(1u32);
+    let mut lowest = 0;
+    for (index, &joltage) in adapters.iter().enumerate().skip(1) {
+        while lowest < index && joltage - adapters[lowest] > tolerance {
+            total -= &counts[lowest];
+            lowest += 1;
+        }
+        counts.push(total.clone());
+        total += &counts[index];
+    }
+    counts
+}
+
+// A sorted chain of `n` adapters with random gaps of up to the tolerance
+// (including duplicates), for benchmarking
+fn synthetic(n: usize, tolerance: u32, rng: &mut Rng) -> Vec<u32> {
+    let mut joltage = 0;
+    let mut adapters = vec![0];
+    for _ in 0..n {
+        joltage += (rng.next_u64() % (tolerance as u64 + 1)) as u32;
+        adapters.push(joltage);
+    }
+    adapters.push(joltage + DEVICE_OFFSET);
+    adapters
+}
+
+fn bench(sizes: &[usize]) {
+    for &n in sizes {
+        let adapters = synthetic(n, TOLERANCE, &mut Rng(n as u64));
+        let now = Instant::now();
+        let iterative = count_arrangements(&adapters, TOLERANCE);
+        let iterative_time = now.elapsed();
+        // Give the recursion room to run
+        let recursive_adapters = adapters.clone();
+        let now = Instant::now();
+        let recursive = std::thread::Builder::new()
+            .stack_size(1 << 30)
+            .spawn(move || {
+                let last = recursive_adapters.len() - 1;
+                num_paths_recursive(&recursive_adapters, TOLERANCE, last, &mut HashMap::new())
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        let recursive_time = now.elapsed();
+        assert_eq!(iterative, recursive);
+        println!(
+            "{} adapters ({} bit count): iterative {}µs, recursive {}µs",
+            n,
+            iterative.bits(),
+            iterative_time.as_micros(),
+            recursive_time.as_micros()
+        );
+    }
+}
+
+// Every valid chain of adapters, generated lazily by a depth-first search
+struct Arrangements<'a> {
+    adapters: &'a [u32],
+    tolerance: u32,
+    // The indices of the chain so far, each with the next index to try after it
+    stack: Vec<(usize, usize)>,
+}
+
+impl Iterator for Arrangements<'_> {
+    type Item = Vec<u32>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, candidate) = *self.stack.last()?;
+            if index == self.adapters.len() - 1 {
+                let chain = self.stack.iter().map(|&(i, _)| self.adapters[i]).collect();
+                self.stack.pop();
+                return Some(chain);
+            }
+            if candidate < self.adapters.len()
+                && self.adapters[candidate] - self.adapters[index] <= self.tolerance
+            {
+                self.stack.last_mut().unwrap().1 += 1;
+                self.stack.push((candidate, candidate + 1));
+            } else {
+                self.stack.pop();
+            }
+        }
+    }
+}
+
+fn arrangements(adapters: &[u32], tolerance: u32) -> Arrangements<'_> {
+    Arrangements {
+        adapters,
+        tolerance,
+        stack: vec![(0, 1)],
+    }
+}
+
+// A small seeded generator (splitmix64), so that samples are reproducible
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..n, by taking just enough random bits and rejecting any
+    // value which is too large
+    fn below(&mut self, n: &BigUint) -> BigUint {
+        assert!(*n > BigUint::from(0u32), "Can't pick below 0");
+        let bits = n.bits();
+        let words = bits.div_ceil(64);
+        loop {
+            let digits: Vec<u32> = (0..words)
+                .flat_map(|_| {
+                    let r = self.next_u64();
+                    vec![r as u32, (r >> 32) as u32]
+                })
+                .collect();
+            let r = BigUint::from_slice(&digits) >> (words * 64 - bits);
+            if r < *n {
+                return r;
+            }
+        }
+    }
+}
+
+// Pick one arrangement uniformly at random. Working back from the device,
+// choose each previous adapter in proportion to the number of paths to it.
+fn sample(adapters: &[u32], counts: &[BigUint], rng: &mut Rng) -> Vec<u32> {
+    let mut index = adapters.len() - 1;
+    let mut chain = vec![adapters[index]];
+    while index > 0 {
+        let mut r = rng.below(&counts[index]);
+        let mut previous = index - 1;
+        while r >= counts[previous] {
+            r -= &counts[previous];
+            previous -= 1;
+        }
+        index = previous;
+        chain.push(adapters[index]);
+    }
+    chain.reverse();
+    chain
+}
+
+// The fewest adapters we can get away with. Jumping to the furthest adapter
+// in reach is never worse than a nearer one, since anything the nearer one
+// can reach further on, so can the furthest.
+fn shortest_chain(adapters: &[u32], tolerance: u32) -> Vec<u32> {
+    let mut index = 0;
+    let mut chain = vec![adapters[0]];
+    while index < adapters.len() - 1 {
+        let mut next = index + 1;
+        while next + 1 < adapters.len() && adapters[next + 1] - adapters[index] <= tolerance {
+            next += 1;
+        }
+        index = next;
+        chain.push(adapters[index]);
+    }
+    chain
+}
+
+// Once the gaps have been checked, every adapter can be used
+fn longest_chain(adapters: &[u32]) -> Vec<u32> {
+    adapters.to_vec()
+}
+
 fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
+    let mut args: Vec<String> = std::env::args().collect();
+    // e.g. `--tolerance 4 --device 5` for adapters which can bridge 4 jolts
+    // and a device rated 5 jolts above the largest adapter
+    let mut flag = |name: &str, default: u32| match args.iter().position(|a| a == name) {
+        Some(i) => {
+            let val = args
+                .get(i + 1)
+                .and_then(|a| a.parse().ok())
+                .unwrap_or_else(|| panic!("Expected a value for {}", name));
+            args.drain(i..(i + 2));
+            val
+        }
+        None => default,
+    };
+    let tolerance = flag("--tolerance", TOLERANCE);
+    let device_offset = flag("--device", DEVICE_OFFSET);
     let input = std::fs::read_to_string("input/10")?;
-    let adapters = parse_input(&input);
+    let adapters = parse_input(&input, device_offset)
+        .and_then(|adapters| check_gaps(&adapters, tolerance).map(|_| adapters))
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if args.get(1).map(String::as_str) == Some("gaps") {
+        // e.g. `cargo run --bin 10 -- gaps` to count every size of gap
+        for (gap, count) in histogram(&adapters) {
+            println!("{} jolt(s): {}", gap, count);
+        }
+        return Ok(());
+    }
+    let show = |chain: &[u32]| {
+        let chain: Vec<String> = chain.iter().map(|j| j.to_string()).collect();
+        chain.join(" ")
+    };
+    match args.get(1).map(String::as_str) {
+        Some("bench") => {
+            // e.g. `cargo run --release --bin 10 -- bench 1000 10000 100000`
+            // to time the iterative and recursive counts on synthetic inputs
+            let sizes: Vec<usize> = args[2..].iter().map(|n| n.parse().unwrap()).collect();
+            bench(&sizes);
+            return Ok(());
+        }
+        Some("list") => {
+            // e.g. `cargo run --bin 10 -- list 5` to show the first 5
+            // arrangements
+            let n = args.get(2).map_or(10, |n| n.parse().unwrap());
+            for chain in arrangements(&adapters, tolerance).take(n) {
+                println!("{}", show(&chain));
+            }
+            return Ok(());
+        }
+        Some("sample") => {
+            // e.g. `cargo run --bin 10 -- sample 42` to pick an arrangement
+            // at random, seeded with 42
+            let seed = args.get(2).map_or(0, |n| n.parse().unwrap());
+            let counts = path_counts(&adapters, tolerance);
+            println!("{}", show(&sample(&adapters, &counts, &mut Rng(seed))));
+            return Ok(());
+        }
+        Some("extremes") => {
+            // e.g. `cargo run --bin 10 -- extremes`
+            let shortest = shortest_chain(&adapters, tolerance);
+            let longest = longest_chain(&adapters);
+            println!("Shortest ({}): {}", shortest.len(), show(&shortest));
+            println!("Longest ({}): {}", longest.len(), show(&longest));
+            return Ok(());
+        }
+        _ => {}
+    }
     println!("Part 1: {}", part_one(&adapters));
-    println!("Part 2: {}", part_two(&adapters));
+    println!("Part 2: {}", part_two(&adapters, tolerance));
     println!("Time: {}µs", now.elapsed().as_micros());
     Ok(())
 }
 
-fn parse_input(input: &str) -> Vec<u32> {
+fn parse_input(input: &str, device_offset: u32) -> Result<Vec<u32>, String> {
     // Return a sorted Vec of the input adapter joltages, including the ingress
     // and egress joltages.
-    let mut raw: Vec<u32> = input.lines().map(|l| l.parse().unwrap()).collect();
+    let mut raw = input
+        .lines()
+        .enumerate()
+        .map(|(i, l)| {
+            l.trim()
+                .parse()
+                .map_err(|e| format!("Line {}: {}", i + 1, e))
+        })
+        .collect::<Result<Vec<u32>, _>>()?;
     raw.push(0);
     raw.sort_unstable();
-    raw.push(raw.last().unwrap() + 3);
-    raw
+    raw.push(raw.last().unwrap() + device_offset);
+    Ok(raw)
+}
+
+// Check that every adapter in the chain can reach the next one
+fn check_gaps(adapters: &[u32], tolerance: u32) -> Result<(), String> {
+    match adapters
+        .windows(2)
+        .find(|pair| pair[1] - pair[0] > tolerance)
+    {
+        Some(pair) => Err(format!(
+            "Gap of {} jolts between {} and {} is larger than the tolerance of {}",
+            pair[1] - pair[0],
+            pair[0],
+            pair[1],
+            tolerance
+        )),
+        None => Ok(()),
+    }
+}
+
+// How many times each difference between consecutive adapters appears
+fn histogram(adapters: &[u32]) -> BTreeMap<u32, usize> {
+    let mut counts = BTreeMap::new();
+    for pair in adapters.windows(2) {
+        *counts.entry(pair[1] - pair[0]).or_insert(0) += 1;
+    }
+    counts
 }
 
 fn part_one(adapters: &[u32]) -> usize {
-    // Map to a Vec of the differences in the adapters.
     // The answer is the number of 1-jolt differences multiplied by the number
     // of 3-jolt differences.
-    let differences: Vec<u32> = adapters.windows(2).map(|pair| pair[1] - pair[0]).collect();
-
-    let jolts_1 = differences.iter().filter(|&d| *d == 1).count();
-    let jolts_3 = differences.iter().filter(|&d| *d == 3).count();
-    jolts_1 * jolts_3
+    let counts = histogram(adapters);
+    let count = |gap| counts.get(&gap).copied().unwrap_or(0);
+    count(1) * count(3)
 }
 
-fn part_two(adapters: &[u32]) -> u64 {
+fn part_two(adapters: &[u32], tolerance: u32) -> BigUint {
     // The number of valid arrangements is the same as the number of paths to
     // the device's adapter.
-    num_paths(adapters, adapters.len() - 1, &mut HashMap::new())
+    count_arrangements(adapters, tolerance)
 }
 
 #[test]
@@ -75,9 +381,9 @@ fn test_examples() {
 6
 12
 4";
-    let numbers = parse_input(short_input);
+    let numbers = parse_input(short_input, DEVICE_OFFSET).unwrap();
     assert_eq!(part_one(&numbers), 35);
-    assert_eq!(part_two(&numbers), 8);
+    assert_eq!(part_two(&numbers, TOLERANCE), BigUint::from(8u32));
 
     let long_input = "28
 33
@@ -110,7 +416,84 @@ fn test_examples() {
 34
 10
 3";
-    let numbers = parse_input(long_input);
+    let numbers = parse_input(long_input, DEVICE_OFFSET).unwrap();
     assert_eq!(part_one(&numbers), 220);
-    assert_eq!(part_two(&numbers), 19208);
+    assert_eq!(part_two(&numbers, TOLERANCE), BigUint::from(19208u32));
+}
+
+#[test]
+fn test_tolerance() {
+    let adapters = parse_input("1\n2\n6\n8", 5).unwrap();
+    assert_eq!(adapters, vec![0, 1, 2, 6, 8, 13]);
+    let counts: Vec<(u32, usize)> = histogram(&adapters).into_iter().collect();
+    assert_eq!(counts, vec![(1, 2), (2, 1), (4, 1), (5, 1)]);
+    assert_eq!(
+        check_gaps(&adapters, 3),
+        Err("Gap of 4 jolts between 2 and 6 is larger than the tolerance of 3".to_string())
+    );
+    assert_eq!(check_gaps(&adapters, 5), Ok(()));
+    // 0-1-2-6-8-13, 0-2-6-8-13, 0-1-6-8-13
+    assert_eq!(part_two(&adapters, 5), BigUint::from(3u32));
+    assert!(parse_input("1\nten", 3).is_err());
+
+    // Every joltage from 1 to 100 gives a tribonacci number of arrangements,
+    // far too many for a u64
+    let input: Vec<String> = (1..=100).map(|n| n.to_string()).collect();
+    let adapters = parse_input(&input.join("\n"), DEVICE_OFFSET).unwrap();
+    assert_eq!(
+        part_two(&adapters, TOLERANCE).to_string(),
+        "180396380815100901214157639"
+    );
+}
+
+#[test]
+fn test_arrangements() {
+    let adapters = parse_input("16\n10\n15\n5\n1\n11\n7\n19\n6\n12\n4", DEVICE_OFFSET).unwrap();
+    let all: Vec<Vec<u32>> = arrangements(&adapters, TOLERANCE).collect();
+    assert_eq!(all.len(), 8);
+    assert_eq!(all[0], adapters);
+    assert_eq!(all[7], vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+    assert_eq!(shortest_chain(&adapters, TOLERANCE), all[7]);
+    assert_eq!(longest_chain(&adapters), all[0]);
+
+    // Every arrangement should come up about equally often, and the same seed
+    // should give the same samples
+    let counts = path_counts(&adapters, TOLERANCE);
+    let mut rng = Rng(2020);
+    let mut hits = vec![0; all.len()];
+    for _ in 0..8000 {
+        let chain = sample(&adapters, &counts, &mut rng);
+        hits[all.iter().position(|c| *c == chain).unwrap()] += 1;
+    }
+    assert!(
+        hits.iter().all(|&hit| 800 < hit && hit < 1200),
+        "{:?}",
+        hits
+    );
+    assert_eq!(
+        sample(&adapters, &counts, &mut Rng(7)),
+        sample(&adapters, &counts, &mut Rng(7))
+    );
+}
+
+#[test]
+fn test_iterative() {
+    let mut rng = Rng(10);
+    for tolerance in 1..5 {
+        let adapters = synthetic(500, tolerance, &mut rng);
+        let recursive = num_paths_recursive(
+            &adapters,
+            tolerance,
+            adapters.len() - 1,
+            &mut HashMap::new(),
+        );
+        assert_eq!(count_arrangements(&adapters, tolerance), recursive);
+        assert_eq!(path_counts(&adapters, tolerance).pop().unwrap(), recursive);
+    }
+    // A long run of equal joltages all share one slot of the window
+    let repeated: Vec<u32> = [0].iter().chain(&[2; 50]).chain(&[5]).copied().collect();
+    assert_eq!(
+        count_arrangements(&repeated, TOLERANCE),
+        num_paths_recursive(&repeated, TOLERANCE, 51, &mut HashMap::new())
+    );
 }
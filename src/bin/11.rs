@@ -12,12 +12,91 @@ const DIRECTIONS: [(isize, isize); 8] = [
     (1, -1),
 ];
 
-#[derive(Copy, Clone)]
-enum Part {
-    Part1,
-    Part2,
+// Which seats a passenger pays attention to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Neighbourhood {
+    // The eight seats around them
+    Adjacent,
+    // The first seat they can see in each of the eight directions
+    Sightline,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct SeatRules {
+    neighbourhood: Neighbourhood,
+    // How far passengers can see along a sightline, if limited
+    max_distance: Option<usize>,
+    // An empty seat is taken if at most this many occupied seats are
+    // noticed (zero in the puzzle)
+    occupy_max: usize,
+    // An occupied seat is left if at least this many are noticed
+    vacate_threshold: usize,
+}
+
+const PART_ONE: SeatRules = SeatRules {
+    neighbourhood: Neighbourhood::Adjacent,
+    max_distance: None,
+    occupy_max: 0,
+    vacate_threshold: 4,
+};
+
+const PART_TWO: SeatRules = SeatRules {
+    neighbourhood: Neighbourhood::Sightline,
+    max_distance: None,
+    occupy_max: 0,
+    vacate_threshold: 5,
+};
+
+// Give up on rules which never settle down after this many rounds
+const MAX_ROUNDS: usize = 10_000;
+
+impl FromStr for SeatRules {
+    type Err = String;
+    // e.g. "part2 distance=3 vacate=4", or the same with one setting per line.
+    // Settings are `neighbourhood=adjacent|sightline`, `distance=<n>|any`,
+    // `occupy=<n>` and `vacate=<n>`, applied on top of the part 1 rules or
+    // the preset named first. Anything after a # is a comment.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut rules = PART_ONE;
+        let settings = input
+            .lines()
+            .map(|line| line.split('#').next().unwrap())
+            .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|setting| !setting.is_empty());
+        for (i, setting) in settings.enumerate() {
+            let number = |val: &str| {
+                val.parse()
+                    .map_err(|_| format!("Expected a number in {:?}", setting))
+            };
+            match setting.split_once('=') {
+                None if i == 0 && setting == "part1" => rules = PART_ONE,
+                None if i == 0 && setting == "part2" => rules = PART_TWO,
+                Some(("neighbourhood", "adjacent")) => {
+                    rules.neighbourhood = Neighbourhood::Adjacent
+                }
+                Some(("neighbourhood", "sightline")) => {
+                    rules.neighbourhood = Neighbourhood::Sightline
+                }
+                Some(("distance", "any")) => rules.max_distance = None,
+                Some(("distance", val)) => rules.max_distance = Some(number(val)?),
+                Some(("occupy", val)) => rules.occupy_max = number(val)?,
+                Some(("vacate", val)) => rules.vacate_threshold = number(val)?,
+                _ => return Err(format!("Bad setting {:?}", setting)),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+impl SeatRules {
+    // How far to look in each direction
+    fn reach(&self) -> Option<usize> {
+        match self.neighbourhood {
+            Neighbourhood::Adjacent => Some(1),
+            Neighbourhood::Sightline => self.max_distance,
+        }
+    }
 }
-use Part::*;
 
 type Seats = Vec<Vec<Position>>;
 
@@ -60,7 +139,7 @@ impl FromStr for SeatMap {
 }
 
 impl SeatMap {
-    fn tick(&mut self, part: Part) -> bool {
+    fn tick(&mut self, rules: &SeatRules) -> bool {
         // Apply the evolution rules once to every position and update self.
         // Return whether or not the map changed.
         let mut changed = false;
@@ -68,10 +147,7 @@ impl SeatMap {
         for r in 0..self.rows {
             let mut new_row = Vec::new();
             for c in 0..self.cols {
-                let new_seat = match part {
-                    Part1 => self.evolve1(r, c),
-                    Part2 => self.evolve2(r, c),
-                };
+                let new_seat = self.evolve(r, c, rules);
                 if !changed && new_seat != self.seats[r][c] {
                     changed = true;
                 }
@@ -83,40 +159,14 @@ impl SeatMap {
         changed
     }
 
-    fn evolve1(&mut self, row: usize, col: usize) -> Position {
-        match self.seats[row][col] {
-            Floor => Floor,
-            Empty => {
-                // If a seat is empty (L) and there are no occupied
-                // seats adjacent to it, the seat becomes occupied.
-                if self.get_occ_neighbours(row as isize, col as isize) == 0 {
-                    Occupied
-                } else {
-                    // Otherwise, the seat's state does not change.
-                    Empty
-                }
-            }
-            Occupied => {
-                // If a seat is occupied (#) and four or more seats
-                // adjacent to it are also occupied, the seat becomes
-                // empty.
-                if self.get_occ_neighbours(row as isize, col as isize) >= 4 {
-                    Empty
-                } else {
-                    // Otherwise, the seat's state does not change.
-                    Occupied
-                }
-            }
-        }
-    }
-
-    fn evolve2(&mut self, row: usize, col: usize) -> Position {
+    fn evolve(&self, row: usize, col: usize, rules: &SeatRules) -> Position {
+        let occupied = || self.count_occupied(row as isize, col as isize, rules.reach());
         match self.seats[row][col] {
             Floor => Floor,
             Empty => {
-                // If a seat is empty (L) and there are no occupied
-                // seats in view, the seat becomes occupied.
-                if self.get_occ_neighbours_sightline(row as isize, col as isize) == 0 {
+                // If a seat is empty (L) and few enough of the seats it
+                // notices are occupied, the seat becomes occupied.
+                if occupied() <= rules.occupy_max {
                     Occupied
                 } else {
                     // Otherwise, the seat's state does not change.
@@ -124,9 +174,9 @@ impl SeatMap {
                 }
             }
             Occupied => {
-                // If a seat is occupied (#) and five or more seats
-                // in view are also occupied, the seat becomes empty.
-                if self.get_occ_neighbours_sightline(row as isize, col as isize) >= 5 {
+                // If a seat is occupied (#) and enough of the seats it
+                // notices are also occupied, the seat becomes empty.
+                if occupied() >= rules.vacate_threshold {
                     Empty
                 } else {
                     // Otherwise, the seat's state does not change.
@@ -136,27 +186,34 @@ impl SeatMap {
         }
     }
 
-    fn get_occ_neighbours(&self, row: isize, col: isize) -> usize {
-        // Return the number of occupied neighbours
-        DIRECTIONS
-            .iter()
-            .map(|p| (p.0 + row, p.1 + col))
-            .filter(|p| self.in_bounds(*p) && self.seats[p.0 as usize][p.1 as usize] == Occupied)
-            .count()
-    }
-
-    fn get_occ_neighbours_sightline(&self, row: isize, col: isize) -> usize {
+    fn count_occupied(&self, row: isize, col: isize, reach: Option<usize>) -> usize {
         // Return the number of visible occupied seats in any direction
         DIRECTIONS
             .iter()
-            .filter(|&d| self.occ_in_sightline(row, col, *d))
+            .filter(|&d| match reach {
+                // Just the neighbouring seat
+                Some(1) => {
+                    let p = (row + d.0, col + d.1);
+                    self.in_bounds(p) && self.seats[p.0 as usize][p.1 as usize] == Occupied
+                }
+                _ => self.occ_in_sightline(row, col, *d, reach),
+            })
             .count()
     }
 
-    fn occ_in_sightline(&self, row: isize, col: isize, direction: (isize, isize)) -> bool {
-        // Return whether or not there is an occupied seat in this direction
+    fn occ_in_sightline(
+        &self,
+        row: isize,
+        col: isize,
+        direction: (isize, isize),
+        reach: Option<usize>,
+    ) -> bool {
+        // Return whether or not there is an occupied seat in this direction,
+        // within reach
         let mut new_pos = (row + direction.0, col + direction.1);
-        while self.in_bounds(new_pos) {
+        let mut remaining = reach.unwrap_or(usize::MAX);
+        while remaining > 0 && self.in_bounds(new_pos) {
+            remaining -= 1;
             match self.seats[new_pos.0 as usize][new_pos.1 as usize] {
                 Occupied => return true,
                 Empty => return false,
@@ -180,6 +237,26 @@ fn main() -> Result<(), std::io::Error> {
     let now = Instant::now();
     let input = std::fs::read_to_string("input/11")?;
     let seat_map = parse_input(&input);
+    let args: Vec<String> = std::env::args().collect();
+    let custom = match args.get(1).map(String::as_str) {
+        // e.g. `cargo run --bin 11 -- rules part2 distance=3 vacate=4`
+        Some("rules") => Some(args[2..].join(" ")),
+        // e.g. `cargo run --bin 11 -- rules-file rules.txt`
+        Some("rules-file") => Some(std::fs::read_to_string(
+            args.get(2).expect("Expected a rules file"),
+        )?),
+        _ => None,
+    };
+    if let Some(spec) = custom {
+        let rules: SeatRules = spec
+            .parse()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        match run_part(&seat_map, &rules) {
+            Some(occupied) => println!("{:?}: {} occupied", rules, occupied),
+            None => println!("{:?}: never settles", rules),
+        }
+        return Ok(());
+    }
     println!("Part 1: {}", part_one(&seat_map));
     println!("Part 2: {}", part_two(&seat_map));
     println!("Time: {}µs", now.elapsed().as_micros());
@@ -190,25 +267,32 @@ fn parse_input(input: &str) -> SeatMap {
     SeatMap::from_str(input).unwrap()
 }
 
-fn run_part(seat_map: &SeatMap, part: Part) -> usize {
+// The number of occupied seats once the map stops changing, if it does
+fn run_part(seat_map: &SeatMap, rules: &SeatRules) -> Option<usize> {
     let mut clone = seat_map.clone();
-    while clone.tick(part) {
-        continue;
+    let mut rounds = 0;
+    while clone.tick(rules) {
+        rounds += 1;
+        if rounds == MAX_ROUNDS {
+            return None;
+        }
     }
-    clone
-        .seats
-        .iter()
-        .flatten()
-        .filter(|&s| s == &Occupied)
-        .count()
+    Some(
+        clone
+            .seats
+            .iter()
+            .flatten()
+            .filter(|&s| s == &Occupied)
+            .count(),
+    )
 }
 
 fn part_one(seat_map: &SeatMap) -> usize {
-    run_part(seat_map, Part1)
+    run_part(seat_map, &PART_ONE).unwrap()
 }
 
 fn part_two(seat_map: &SeatMap) -> usize {
-    run_part(seat_map, Part2)
+    run_part(seat_map, &PART_TWO).unwrap()
 }
 
 #[test]
@@ -227,3 +311,33 @@ L.LLLLL.LL";
     assert_eq!(part_one(&seat_map), 37);
     assert_eq!(part_two(&seat_map), 26);
 }
+
+#[test]
+fn test_rules() {
+    assert_eq!("part2".parse(), Ok(PART_TWO));
+    assert_eq!(
+        "neighbourhood=sightline, vacate=5\n# as in part 2".parse(),
+        Ok(PART_TWO)
+    );
+    let rules: SeatRules = "part2 distance=1 vacate=4".parse().unwrap();
+    assert_eq!(rules.reach(), Some(1));
+    assert!("vacate=lots".parse::<SeatRules>().is_err());
+    assert!("distance=2 part2".parse::<SeatRules>().is_err());
+
+    let input = "L.LL.LL.LL
+LLLLLLL.LL
+L.L.L..L..
+LLLL.LL.LL
+L.LL.LL.LL
+L.LLLLL.LL
+..L.L.....
+LLLLLLLLLL
+L.LLLLLL.L
+L.LLLLL.LL";
+    let seat_map = parse_input(input);
+    // Sightlines of length one are the same as adjacency
+    assert_eq!(run_part(&seat_map, &rules), Some(37));
+    // Everyone leaves at once, then everyone sits down again
+    let flicker: SeatRules = "occupy=8 vacate=0".parse().unwrap();
+    assert_eq!(run_part(&seat_map, &flicker), None);
+}
@@ -92,7 +92,7 @@ fn do_cardinal_move(original: (i32, i32), action: &Action) -> (i32, i32) {
 }
 
 fn manhattan_distance(pos: (i32, i32)) -> u32 {
-    pos.0.abs() as u32 + pos.1.abs() as u32
+    pos.0.unsigned_abs() + pos.1.unsigned_abs()
 }
 
 fn main() -> Result<(), std::io::Error> {
@@ -81,7 +81,7 @@ fn part_two(buses: &[Bus]) -> u64 {
 fn test_example() {
     let input = "939
 7,13,x,x,59,x,31,19";
-    let (earliest, buses) = parse_input(&input);
+    let (earliest, buses) = parse_input(input);
     assert_eq!(part_one(earliest, &buses), 295);
     assert_eq!(part_two(&buses), 1068781);
 }
@@ -90,21 +90,21 @@ fn test_example() {
 fn test_extra_part_two_examples() {
     let test1 = "0
 67,7,59,61";
-    let (_, buses) = parse_input(&test1);
+    let (_, buses) = parse_input(test1);
     assert_eq!(part_two(&buses), 754018);
 
     let test2 = "0
 67,x,7,59,61";
-    let (_, buses) = parse_input(&test2);
+    let (_, buses) = parse_input(test2);
     assert_eq!(part_two(&buses), 779210);
 
     let test3 = "0
 67,7,x,59,61";
-    let (_, buses) = parse_input(&test3);
+    let (_, buses) = parse_input(test3);
     assert_eq!(part_two(&buses), 1261476);
 
     let test4 = "0
 1789,37,47,1889";
-    let (_, buses) = parse_input(&test4);
+    let (_, buses) = parse_input(test4);
     assert_eq!(part_two(&buses), 1202161486);
 }
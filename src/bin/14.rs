@@ -87,7 +87,7 @@ fn masked2(mut value: u64, masks: &(u64, Vec<u64>)) -> Vec<u64> {
         for addr in addresses.iter() {
             new_addresses.push(addr & x_mask);
         }
-        addresses.extend(new_addresses.into_iter());
+        addresses.extend(new_addresses);
     }
     addresses
 }
@@ -149,7 +149,7 @@ fn test_example_one() {
 mem[8] = 11
 mem[7] = 101
 mem[8] = 0";
-    let commands = parse_input(&input);
+    let commands = parse_input(input);
     assert_eq!(part_one(&commands), 165);
 }
 
@@ -159,6 +159,6 @@ fn test_example_two() {
 mem[42] = 100
 mask = 00000000000000000000000000000000X0XX
 mem[26] = 1";
-    let commands = parse_input(&input);
+    let commands = parse_input(input);
     assert_eq!(part_two(&commands), 208);
 }
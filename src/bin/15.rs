@@ -80,7 +80,7 @@ fn test_part_one_examples() {
     // Tests are run in separate threads with less stack size, so this test
     // needs to be run with `RUST_MIN_STACK=5000000 cargo test -- --ignored`
     let input = "0,3,6";
-    let starting_numbers = parse_input(&input);
+    let starting_numbers = parse_input(input);
     assert_eq!(part_one(&starting_numbers), 436);
 
     let starting_numbers = vec![1, 3, 2];
@@ -25,7 +25,6 @@ fn main() -> Result<(), std::io::Error> {
 
 fn parse_input(input: &str) -> AllInfo {
     let mut fields: HashMap<FieldName, HashSet<u32>> = HashMap::new();
-    let our_ticket: Ticket;
     let mut nearby_tickets: Vec<Ticket> = Vec::new();
 
     let mut lines = input.lines();
@@ -49,7 +48,7 @@ fn parse_input(input: &str) -> AllInfo {
 
     // Parse our ticket
     line = lines.nth(1).unwrap();
-    our_ticket = line.split(',').map(|n| n.parse().unwrap()).collect();
+    let our_ticket: Ticket = line.split(',').map(|n| n.parse().unwrap()).collect();
 
     // Skip two lines
     let _ = lines.nth(1);
@@ -61,7 +60,7 @@ fn parse_input(input: &str) -> AllInfo {
 
     let all_valid_values: HashSet<u32> = fields
         .values()
-        .fold(HashSet::new(), |acc, s| acc.union(&s).cloned().collect());
+        .fold(HashSet::new(), |acc, s| acc.union(s).cloned().collect());
     AllInfo {
         fields,
         our_ticket,
@@ -86,8 +85,8 @@ fn part_two(all_info: &AllInfo) -> u64 {
     let valid_tickets: Vec<Vec<u32>> = all_info
         .nearby_tickets
         .iter()
+        .filter(|&t| t.iter().all(|v| all_info.all_valid_values.contains(v)))
         .cloned()
-        .filter(|t| t.iter().all(|v| all_info.all_valid_values.contains(v)))
         .collect();
     let mut to_consider: HashSet<usize> = (0..valid_tickets[0].len()).collect();
     let mut possible_mappings: HashMap<usize, HashSet<FieldName>> = to_consider
@@ -152,7 +151,7 @@ nearby tickets:
 40,4,50
 55,2,20
 38,6,12";
-    let all_info = parse_input(&input);
+    let all_info = parse_input(input);
     assert_eq!(part_one(&all_info), 71);
 
     let input = "class: 0-1 or 4-19
@@ -166,6 +165,6 @@ nearby tickets:
 3,9,18
 15,1,5
 5,14,9";
-    let all_info = parse_input(&input);
+    let all_info = parse_input(input);
     assert_eq!(part_two(&all_info), 11);
 }
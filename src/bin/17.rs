@@ -72,7 +72,7 @@ impl ConwayCube {
     fn get_neighbours(&self, point: &[i32]) -> Vec<Position> {
         let mut neighbours = Vec::new();
         for d in &self.directions {
-            neighbours.push(vector_add(&point, &d));
+            neighbours.push(vector_add(point, d));
         }
         neighbours
     }
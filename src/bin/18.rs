@@ -101,10 +101,7 @@ fn parse_input(input: &str) -> Vec<Expression> {
                     ')' => Some(Token::Op(OpType::RB)),
                     '+' => Some(Token::Op(OpType::Plus)),
                     '*' => Some(Token::Op(OpType::Multiply)),
-                    maybe_num => match maybe_num.to_digit(10) {
-                        Some(n) => Some(Token::Number(n as u64)),
-                        None => None,
-                    },
+                    maybe_num => maybe_num.to_digit(10).map(|n| Token::Number(n as u64)),
                 })
                 .collect(),
         )
@@ -139,22 +136,22 @@ fn part_two(expressions: &[Expression]) -> u64 {
 #[test]
 fn test_examples() {
     let input = "2 * 3 + (4 * 5)";
-    let expressions = parse_input(&input);
+    let expressions = parse_input(input);
     assert_eq!(part_one(&expressions), 26);
     assert_eq!(part_two(&expressions), 46);
 
     let input = "5 + (8 * 3 + 9 + 3 * 4 * 3)";
-    let expressions = parse_input(&input);
+    let expressions = parse_input(input);
     assert_eq!(part_one(&expressions), 437);
     assert_eq!(part_two(&expressions), 1445);
 
     let input = "5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))";
-    let expressions = parse_input(&input);
+    let expressions = parse_input(input);
     assert_eq!(part_one(&expressions), 12240);
     assert_eq!(part_two(&expressions), 669060);
 
     let input = "((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2";
-    let expressions = parse_input(&input);
+    let expressions = parse_input(input);
     assert_eq!(part_one(&expressions), 13632);
     assert_eq!(part_two(&expressions), 23340);
 }
@@ -8,7 +8,7 @@ enum Pixel {
 }
 
 impl Pixel {
-    fn to_digit(&self) -> char {
+    fn to_digit(self) -> char {
         match self {
             Self::Fill => '1',
             Self::Empty => '0',
@@ -162,12 +162,7 @@ fn solve_jigsaw(tiles: &mut Vec<Tile>) {
 
     while let Some(mut tile_to_process) = tiles.pop() {
         tile_to_process.orientation_fixed = true;
-        for (i, side) in tile_to_process
-            .sides
-            .iter()
-            .map(|s| reverse_side(s))
-            .enumerate()
-        {
+        for (i, side) in tile_to_process.sides.iter().map(reverse_side).enumerate() {
             // We're hunting for another tile with the reversed side to match
             // with ours.
 
@@ -228,19 +223,14 @@ fn trim_pixels(pixels: &[Vec<Pixel>]) -> Vec<Vec<Pixel>> {
 
 fn rotate_pixels(pixels: &mut Vec<Vec<Pixel>>) {
     // Rotates 90 degrees clockwise
-    let mut new_pixels: Vec<Vec<Pixel>> = Vec::new();
     let l = pixels[0].len();
-    for i in 0..l {
-        let mut new_row: Vec<Pixel> = Vec::new();
-        for j in 1..=l {
-            new_row.push(pixels[l - j][i]);
-        }
-        new_pixels.push(new_row);
-    }
+    let new_pixels: Vec<Vec<Pixel>> = (0..l)
+        .map(|i| pixels.iter().rev().map(|row| row[i]).collect())
+        .collect();
     *pixels = new_pixels;
 }
 
-fn flip_pixels(pixels: &mut Vec<Vec<Pixel>>) {
+fn flip_pixels(pixels: &mut [Vec<Pixel>]) {
     // Flips left-right
     for row in pixels.iter_mut() {
         row.reverse();
@@ -371,7 +361,7 @@ fn part_two(tiles: &[Tile]) -> usize {
 }
 
 fn parse_input(input: &str) -> Vec<Tile> {
-    input.split("\n\n").map(|t| Tile::from_str(t)).collect()
+    input.split("\n\n").map(Tile::from_str).collect()
 }
 
 #[test]
@@ -483,7 +473,7 @@ Tile 3079:
 ..#.###...
 ..#.......
 ..#.###...";
-    let mut tiles = parse_input(&input);
+    let mut tiles = parse_input(input);
     assert_eq!(part_one(&mut tiles), 20899048083289);
-    assert_eq!(part_two(&mut tiles), 273);
+    assert_eq!(part_two(&tiles), 273);
 }
@@ -48,7 +48,7 @@ fn main() -> Result<(), std::io::Error> {
 }
 
 fn parse_input(input: &str) -> Vec<Recipe> {
-    input.lines().map(|l| Recipe::from_str(l)).collect()
+    input.lines().map(Recipe::from_str).collect()
 }
 
 fn get_allergenic_ingredients(recipes: &[Recipe]) -> HashMap<Ingredient, Allergen> {
@@ -116,8 +116,7 @@ fn get_allergenic_ingredients(recipes: &[Recipe]) -> HashMap<Ingredient, Allerge
 fn part_one(recipes: &[Recipe], allergenic_ingredients: &HashMap<Ingredient, Allergen>) -> usize {
     recipes
         .iter()
-        .map(|recipe| recipe.ingredients.clone())
-        .flatten()
+        .flat_map(|recipe| recipe.ingredients.clone())
         .filter(|i| !allergenic_ingredients.contains_key(i))
         .count()
 }
@@ -134,7 +133,7 @@ fn test_examples() {
 trh fvjkl sbzzf mxmxvkd (contains dairy)
 sqjhc fvjkl (contains soy)
 sqjhc mxmxvkd sbzzf (contains fish)";
-    let recipes = parse_input(&input);
+    let recipes = parse_input(input);
     let allergenic_ingredients = get_allergenic_ingredients(&recipes);
     assert_eq!(part_one(&recipes, &allergenic_ingredients), 5);
     assert_eq!(part_two(&allergenic_ingredients), "mxmxvkd,sqjhc,fvjkl")
@@ -104,7 +104,7 @@ fn part_two(input: &str) -> u64 {
 #[test]
 fn test_part_one_example() {
     let input = "389125467";
-    assert_eq!(part_one(&input), "67384529");
+    assert_eq!(part_one(input), "67384529");
 }
 
 #[test]
@@ -113,5 +113,5 @@ fn test_part_two_example() {
     // This test takes a while to run, so is ignored.
     // It can be run with `cargo test -- --ignored`
     let input = "389125467";
-    assert_eq!(part_two(&input), 149245887792);
+    assert_eq!(part_two(input), 149245887792);
 }
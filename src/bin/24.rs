@@ -168,7 +168,7 @@ nenewswnwewswnenesenwnesewesw
 eneswnwswnwsenenwnwnwwseeswneewsenese
 neswnwewnwnwseenwseesewsenwsweewe
 wseweeenwnesenwwwswnew";
-    let displacements = parse_input(&input);
+    let displacements = parse_input(input);
     assert_eq!(part_one(&displacements), 10);
     assert_eq!(part_two(&displacements), 2208);
 }
@@ -1,3 +1,5 @@
+pub mod vm;
+
 pub struct AllPos<'a> {
     input: &'a str,
     position: usize,
@@ -20,7 +22,7 @@ impl Iterator for AllPos<'_> {
     }
 }
 
-pub fn find_all_positions(input: &str, search: char) -> AllPos {
+pub fn find_all_positions(input: &str, search: char) -> AllPos<'_> {
     AllPos {
         input,
         position: 0,
@@ -31,7 +33,7 @@ pub fn find_all_positions(input: &str, search: char) -> AllPos {
 #[test]
 fn test_find_all_pos() {
     let input = "abc def g h  ";
-    let mut space_positions = find_all_positions(&input, ' ');
+    let mut space_positions = find_all_positions(input, ' ');
     assert_eq!(space_positions.next(), Some(3));
     assert_eq!(space_positions.next(), Some(7));
     assert_eq!(space_positions.next(), Some(9));
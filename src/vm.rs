@@ -0,0 +1,944 @@
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+// An argument to an instruction - either a register (stored as an index into
+// the register file) or a literal value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(usize),
+    Value(i64),
+}
+
+impl Operand {
+    pub fn get(self, registers: &[i64]) -> i64 {
+        match self {
+            Operand::Register(r) => registers[r],
+            Operand::Value(val) => val,
+        }
+    }
+
+    // Only valid for parameters declared as `Param::Register`, which the
+    // parser guarantees
+    pub fn register(self) -> usize {
+        match self {
+            Operand::Register(r) => r,
+            Operand::Value(val) => panic!("Expected a register, got {}", val),
+        }
+    }
+}
+
+// What an operation accepts in each argument position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    Register,
+    Any,
+    // A relative jump, which may also be written as a label
+    Offset,
+}
+
+// What happens to the program counter after an instruction executes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Next,
+    Jump(i64),
+    Halt,
+    Overflow,
+}
+
+pub type Exec = fn(&mut [i64], &[Operand]) -> Flow;
+
+#[derive(Clone, Copy)]
+pub struct Op {
+    pub name: &'static str,
+    pub params: &'static [Param],
+    pub exec: Exec,
+}
+
+impl fmt::Debug for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl PartialEq for Op {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Op {}
+
+fn arithmetic(registers: &mut [i64], args: &[Operand], f: fn(i64, i64) -> Option<i64>) -> Flow {
+    let r = args[0].register();
+    match f(registers[r], args[1].get(registers)) {
+        Some(val) => {
+            registers[r] = val;
+            Flow::Next
+        }
+        None => Flow::Overflow,
+    }
+}
+
+fn jump_if(registers: &[i64], args: &[Operand], condition: fn(i64) -> bool) -> Flow {
+    match condition(args[0].get(registers)) {
+        true => Flow::Jump(args[1].get(registers)),
+        false => Flow::Next,
+    }
+}
+
+// The handheld's instructions. `acc` always adds to the first register.
+pub const ACC: Op = Op {
+    name: "acc",
+    params: &[Param::Any],
+    exec: |registers, args| match registers[0].checked_add(args[0].get(registers)) {
+        Some(val) => {
+            registers[0] = val;
+            Flow::Next
+        }
+        None => Flow::Overflow,
+    },
+};
+pub const JMP: Op = Op {
+    name: "jmp",
+    params: &[Param::Offset],
+    exec: |registers, args| Flow::Jump(args[0].get(registers)),
+};
+pub const NOP: Op = Op {
+    name: "nop",
+    params: &[Param::Any],
+    exec: |_, _| Flow::Next,
+};
+
+// General purpose instructions, for puzzles with more than one register
+pub const ADD: Op = Op {
+    name: "add",
+    params: &[Param::Register, Param::Any],
+    exec: |registers, args| arithmetic(registers, args, i64::checked_add),
+};
+pub const MUL: Op = Op {
+    name: "mul",
+    params: &[Param::Register, Param::Any],
+    exec: |registers, args| arithmetic(registers, args, i64::checked_mul),
+};
+pub const CPY: Op = Op {
+    name: "cpy",
+    params: &[Param::Any, Param::Register],
+    exec: |registers, args| {
+        registers[args[1].register()] = args[0].get(registers);
+        Flow::Next
+    },
+};
+pub const JNZ: Op = Op {
+    name: "jnz",
+    params: &[Param::Any, Param::Offset],
+    exec: |registers, args| jump_if(registers, args, |val| val != 0),
+};
+pub const JGZ: Op = Op {
+    name: "jgz",
+    params: &[Param::Any, Param::Offset],
+    exec: |registers, args| jump_if(registers, args, |val| val > 0),
+};
+pub const HLT: Op = Op {
+    name: "hlt",
+    params: &[],
+    exec: |_, _| Flow::Halt,
+};
+
+// Operands are stored inline so that instructions are cheap to copy
+pub const MAX_ARGS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub op: Op,
+    operands: [Operand; MAX_ARGS],
+}
+
+impl Instruction {
+    pub fn new(op: Op, args: &[Operand]) -> Self {
+        assert_eq!(
+            args.len(),
+            op.params.len(),
+            "Wrong number of arguments to {}",
+            op.name
+        );
+        let mut operands = [Operand::Value(0); MAX_ARGS];
+        operands[..args.len()].copy_from_slice(args);
+        Instruction { op, operands }
+    }
+
+    pub fn args(&self) -> &[Operand] {
+        &self.operands[..self.op.params.len()]
+    }
+}
+
+// The table of operations and registers that a program may use
+#[derive(Debug, Clone)]
+pub struct InstructionSet {
+    ops: Vec<Op>,
+    registers: Vec<String>,
+}
+
+impl InstructionSet {
+    pub fn new(registers: &[&str]) -> Self {
+        InstructionSet {
+            ops: Vec::new(),
+            registers: registers.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    // The day 08 handheld: a single accumulator and acc/jmp/nop
+    pub fn handheld() -> Self {
+        InstructionSet::new(&["acc"]).with(ACC).with(JMP).with(NOP)
+    }
+
+    // Everything above, over the given registers
+    pub fn extended(registers: &[&str]) -> Self {
+        [ADD, MUL, CPY, JNZ, JGZ, HLT]
+            .iter()
+            .fold(InstructionSet::handheld(), |set, &op| set.with(op))
+            .with_registers(registers)
+    }
+
+    // Add (or replace) an operation
+    pub fn with(mut self, op: Op) -> Self {
+        assert!(
+            op.params.len() <= MAX_ARGS,
+            "{} takes too many arguments",
+            op.name
+        );
+        self.ops.retain(|existing| existing.name != op.name);
+        self.ops.push(op);
+        self
+    }
+
+    pub fn with_registers(mut self, registers: &[&str]) -> Self {
+        self.registers = registers.iter().map(|r| r.to_string()).collect();
+        self
+    }
+
+    pub fn op(&self, name: &str) -> Option<Op> {
+        self.ops.iter().find(|op| op.name == name).copied()
+    }
+
+    pub fn register(&self, name: &str) -> Option<usize> {
+        self.registers.iter().position(|r| r == name)
+    }
+
+    pub fn registers(&self) -> &[String] {
+        &self.registers
+    }
+
+    pub fn parse_instruction(&self, line: &str) -> Result<Instruction, String> {
+        self.parse_line(line, 0, &HashMap::new())
+    }
+
+    // Labels are resolved to offsets relative to `pc`
+    fn parse_line(
+        &self,
+        line: &str,
+        pc: usize,
+        labels: &HashMap<&str, usize>,
+    ) -> Result<Instruction, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("Empty instruction")?;
+        let op = self
+            .op(name)
+            .ok_or(format!("Unknown operation {:?} in {:?}", name, line))?;
+        let words: Vec<&str> = words.collect();
+        if words.len() != op.params.len() {
+            return Err(format!(
+                "{} takes {} argument(s), got {} in {:?}",
+                op.name,
+                op.params.len(),
+                words.len(),
+                line
+            ));
+        }
+        let args = words
+            .iter()
+            .zip(op.params)
+            .map(|(word, param)| {
+                match (word.parse(), self.register(word), labels.get(word), param) {
+                    (Ok(val), _, _, Param::Any) | (Ok(val), _, _, Param::Offset) => {
+                        Ok(Operand::Value(val))
+                    }
+                    (_, Some(r), _, _) => Ok(Operand::Register(r)),
+                    (_, _, Some(&target), Param::Offset) => {
+                        Ok(Operand::Value(target as i64 - pc as i64))
+                    }
+                    (Ok(_), _, _, Param::Register) => {
+                        Err(format!("Expected a register, got {:?} in {:?}", word, line))
+                    }
+                    (_, _, Some(_), _) => Err(format!(
+                        "Label {:?} can only be used as a jump offset in {:?}",
+                        word, line
+                    )),
+                    _ => Err(format!("Bad argument {:?} in {:?}", word, line)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Instruction::new(op, &args))
+    }
+
+    // Assemble a program. Blank lines and `;` comments are ignored, and a line
+    // `name:` labels the next instruction so that jumps can target `name`.
+    pub fn parse(self, input: &str) -> Result<Program, String> {
+        let mut labels = HashMap::new();
+        let mut lines = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            let line = line.split(';').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.strip_suffix(':') {
+                Some(label) => {
+                    if label.is_empty()
+                        || !label.chars().all(|c| c.is_alphanumeric() || c == '_')
+                        || label.parse::<i64>().is_ok()
+                        || self.register(label).is_some()
+                    {
+                        return Err(format!("Line {}: Bad label {:?}", i + 1, label));
+                    }
+                    if labels.insert(label, lines.len()).is_some() {
+                        return Err(format!("Line {}: Duplicate label {:?}", i + 1, label));
+                    }
+                }
+                None => lines.push((i, line)),
+            }
+        }
+        let instructions = lines
+            .iter()
+            .enumerate()
+            .map(|(pc, (i, line))| {
+                self.parse_line(line, pc, &labels)
+                    .map_err(|e| format!("Line {}: {}", i + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Program::new(Rc::new(self), instructions))
+    }
+
+    fn format_operand(&self, operand: Operand) -> String {
+        match operand {
+            Operand::Register(r) => self.registers[r].clone(),
+            Operand::Value(val) => format!("{:+}", val),
+        }
+    }
+
+    pub fn format(&self, instruction: &Instruction) -> String {
+        let mut out = instruction.op.name.to_string();
+        for arg in instruction.args() {
+            out += " ";
+            out += &self.format_operand(*arg);
+        }
+        out
+    }
+
+    // Read a program written by `Program::encode`
+    pub fn decode(self, bytes: &[u8]) -> Result<Program, String> {
+        let mut bytes = bytes
+            .strip_prefix(MAGIC)
+            .ok_or("Not an encoded program")?
+            .iter();
+        let len = read_varint(&mut bytes)?;
+        let mut instructions = Vec::new();
+        for pc in 0..len {
+            let op = *usize::try_from(read_varint(&mut bytes)?)
+                .ok()
+                .and_then(|opcode| self.ops.get(opcode))
+                .ok_or(format!("Bad opcode for instruction {}", pc))?;
+            let mut args = Vec::new();
+            for param in op.params {
+                let encoded = read_varint(&mut bytes)?;
+                let arg = match (encoded & 1, param) {
+                    (0, Param::Register) => {
+                        return Err(format!("Expected a register in instruction {}", pc))
+                    }
+                    (0, _) => match u64::try_from(encoded >> 1) {
+                        Ok(zigzag) => Operand::Value((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64)),
+                        _ => return Err(format!("Value too large in instruction {}", pc)),
+                    },
+                    _ => match usize::try_from(encoded >> 1) {
+                        Ok(r) if r < self.registers.len() => Operand::Register(r),
+                        _ => return Err(format!("Bad register in instruction {}", pc)),
+                    },
+                };
+                args.push(arg);
+            }
+            instructions.push(Instruction::new(op, &args));
+        }
+        if bytes.next().is_some() {
+            return Err("Trailing bytes after program".to_string());
+        }
+        Ok(Program::new(Rc::new(self), instructions))
+    }
+}
+
+// The binary encoding is this header, then the number of instructions, then
+// for each instruction its index in the instruction set's table followed by
+// its operands - all as LEB128 varints. Operands are tagged in the low bit:
+// registers by index, values zigzag encoded so that small negative jumps
+// stay small.
+const MAGIC: &[u8] = b"VM\x01";
+
+fn write_varint(out: &mut Vec<u8>, mut val: u128) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn read_varint<'a>(bytes: &mut impl Iterator<Item = &'a u8>) -> Result<u128, String> {
+    let mut val = 0;
+    for shift in (0..128).step_by(7) {
+        let byte = bytes.next().ok_or("Unexpected end of program")?;
+        val |= ((byte & 0x7f) as u128) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+    Err("Varint too long".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramHalt {
+    // Ran off the end of the program, or hit a halting instruction
+    NormalExit,
+    // About to execute an instruction for the second time
+    Loop,
+    // Tried to jump to the given target
+    OutOfBounds(i64),
+    // Executed the maximum number of instructions
+    LimitReached,
+    // Arithmetic overflowed a register
+    Overflow,
+}
+
+// One executed instruction, and how much it changed the accumulator by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceStep {
+    pub pc: usize,
+    pub delta: i64,
+}
+
+// A loop: the instruction about to be repeated, and every step since it was
+// first executed
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cycle<'a> {
+    pub entry: usize,
+    pub body: &'a [TraceStep],
+}
+
+// A record of everything a program executed, and where it ended up
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub steps: Vec<TraceStep>,
+    pub end: usize,
+    // Why the program stopped, once it has
+    pub halt: Option<ProgramHalt>,
+}
+
+impl Trace {
+    // If the program stopped because it was about to repeat an instruction, the
+    // steps from that instruction onwards form the loop
+    pub fn cycle(&self) -> Option<Cycle<'_>> {
+        if self.halt != Some(ProgramHalt::Loop) {
+            return None;
+        }
+        self.steps
+            .iter()
+            .position(|step| step.pc == self.end)
+            .map(|start| Cycle {
+                entry: self.end,
+                body: &self.steps[start..],
+            })
+    }
+
+    pub fn hit_counts(&self, len: usize) -> Vec<usize> {
+        let mut hits = vec![0; len];
+        for step in &self.steps {
+            hits[step.pc] += 1;
+        }
+        hits
+    }
+
+    // One line per instruction, with a marker for those in the loop
+    pub fn to_text(&self, program: &Program) -> String {
+        let hits = self.hit_counts(program.instructions.len());
+        let cycle = self.cycle();
+        let in_cycle = |pc| {
+            cycle
+                .as_ref()
+                .is_some_and(|c| c.body.iter().any(|step| step.pc == pc))
+        };
+        let mut out = format!("{} steps, ending at {}\n", self.steps.len(), self.end);
+        match &cycle {
+            Some(cycle) => {
+                out += &format!(
+                    "Loop of {} steps entered at {}, changing acc by {} each time\n",
+                    cycle.body.len(),
+                    cycle.entry,
+                    cycle.body.iter().map(|step| step.delta).sum::<i64>()
+                )
+            }
+            None => out += "No loop\n",
+        }
+        for (pc, instruction) in program.instructions.iter().enumerate() {
+            out += &format!(
+                "{:>5} {:>6} {} {}\n",
+                pc,
+                hits[pc],
+                if in_cycle(pc) { '*' } else { ' ' },
+                program.format(instruction)
+            );
+        }
+        out
+    }
+
+    pub fn to_json(&self, program: &Program) -> String {
+        let hits = self.hit_counts(program.instructions.len());
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|step| format!("{{\"pc\":{},\"delta\":{}}}", step.pc, step.delta))
+            .collect();
+        let cycle = match self.cycle() {
+            Some(cycle) => format!(
+                "{{\"entry\":{},\"body\":[{}]}}",
+                cycle.entry,
+                cycle
+                    .body
+                    .iter()
+                    .map(|step| step.pc.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            None => "null".to_string(),
+        };
+        let hits: Vec<String> = hits.iter().map(|hit| hit.to_string()).collect();
+        format!(
+            "{{\"end\":{},\"cycle\":{},\"hits\":[{}],\"steps\":[{}]}}",
+            self.end,
+            cycle,
+            hits.join(","),
+            steps.join(",")
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    set: Rc<InstructionSet>,
+    pub instructions: Vec<Instruction>,
+    pub registers: Vec<i64>,
+    pub program_counter: usize,
+    // How many instructions have been executed, and an optional cap on that
+    pub executed: usize,
+    pub limit: Option<usize>,
+    // Only recorded if asked for, with `with_trace`
+    pub trace: Option<Trace>,
+}
+
+impl Program {
+    pub fn new(set: Rc<InstructionSet>, instructions: Vec<Instruction>) -> Self {
+        Program {
+            registers: vec![0; set.registers.len()],
+            set,
+            instructions,
+            program_counter: 0,
+            executed: 0,
+            limit: None,
+            trace: None,
+        }
+    }
+
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Trace {
+            steps: Vec::new(),
+            end: self.program_counter,
+            halt: None,
+        });
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn instruction_set(&self) -> &InstructionSet {
+        &self.set
+    }
+
+    pub fn format(&self, instruction: &Instruction) -> String {
+        self.set.format(instruction)
+    }
+
+    // The first register - the handheld's accumulator
+    pub fn accumulator(&self) -> i64 {
+        self.registers[0]
+    }
+
+    // Execute the instruction at the program counter. Returns why the program
+    // can't continue, if it can't - in which case the program counter is left
+    // pointing at the offending instruction.
+    pub fn step(&mut self) -> Option<ProgramHalt> {
+        let halt = self.execute();
+        if halt.is_some() {
+            self.record_halt(halt);
+        }
+        halt
+    }
+
+    fn record_halt(&mut self, halt: Option<ProgramHalt>) {
+        if let Some(trace) = &mut self.trace {
+            trace.halt = halt;
+        }
+    }
+
+    fn execute(&mut self) -> Option<ProgramHalt> {
+        let pc = self.program_counter;
+        let instruction = match self.instructions.get(pc) {
+            Some(instruction) => instruction,
+            None => return Some(ProgramHalt::NormalExit),
+        };
+        if self.limit.is_some_and(|limit| self.executed >= limit) {
+            return Some(ProgramHalt::LimitReached);
+        }
+        let before = self.registers.first().copied().unwrap_or(0);
+        let target = match (instruction.op.exec)(&mut self.registers, instruction.args()) {
+            Flow::Next => pc as i64 + 1,
+            Flow::Jump(offset) => match (pc as i64).checked_add(offset) {
+                Some(target) => target,
+                None => return Some(ProgramHalt::OutOfBounds(offset)),
+            },
+            Flow::Halt => self.instructions.len() as i64,
+            Flow::Overflow => return Some(ProgramHalt::Overflow),
+        };
+        // Jumping to just past the last instruction is a normal exit
+        if target < 0 || target > self.instructions.len() as i64 {
+            return Some(ProgramHalt::OutOfBounds(target));
+        }
+        self.program_counter = target as usize;
+        self.executed += 1;
+        if let Some(trace) = &mut self.trace {
+            let after = self.registers.first().copied().unwrap_or(0);
+            trace.steps.push(TraceStep {
+                pc,
+                delta: after.wrapping_sub(before),
+            });
+            trace.end = self.program_counter;
+        }
+        None
+    }
+
+    // Run until the program stops of its own accord (or hits the limit)
+    pub fn run(&mut self) -> ProgramHalt {
+        loop {
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+
+    // Run, but stop before executing any instruction a second time
+    pub fn run_until_repeat(&mut self) -> ProgramHalt {
+        let mut seen_positions = vec![false; self.instructions.len()];
+        loop {
+            if let Some(seen) = seen_positions.get_mut(self.program_counter) {
+                if *seen {
+                    self.record_halt(Some(ProgramHalt::Loop));
+                    return ProgramHalt::Loop;
+                }
+                *seen = true;
+            }
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+}
+
+impl Program {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        write_varint(&mut out, self.instructions.len() as u128);
+        for instruction in &self.instructions {
+            let opcode = self
+                .set
+                .ops
+                .iter()
+                .position(|op| *op == instruction.op)
+                .expect("Instruction not in the program's instruction set");
+            write_varint(&mut out, opcode as u128);
+            for arg in instruction.args() {
+                let encoded = match *arg {
+                    Operand::Value(val) => (((val << 1) ^ (val >> 63)) as u64 as u128) << 1,
+                    Operand::Register(r) => (r as u128) << 1 | 1,
+                };
+                write_varint(&mut out, encoded);
+            }
+        }
+        out
+    }
+
+    // Like `Display`, but jumps to within the program go to labels instead
+    pub fn disassemble(&self) -> String {
+        let len = self.instructions.len();
+        let target = |pc: usize, param: &Param, arg: &Operand| match (param, arg) {
+            (Param::Offset, Operand::Value(offset)) => (pc as i64)
+                .checked_add(*offset)
+                .filter(|target| (0..=len as i64).contains(target))
+                .map(|target| target as usize),
+            _ => None,
+        };
+        let targets: BTreeSet<usize> = self
+            .instructions
+            .iter()
+            .enumerate()
+            .flat_map(|(pc, instruction)| {
+                instruction
+                    .op
+                    .params
+                    .iter()
+                    .zip(instruction.args())
+                    .filter_map(move |(param, arg)| target(pc, param, arg))
+            })
+            .collect();
+        // Skip any names already taken by registers, so the output parses
+        let mut names = (0..)
+            .map(|i| format!("L{}", i))
+            .filter(|name| self.set.register(name).is_none());
+        let labels: HashMap<usize, String> = targets
+            .iter()
+            .map(|&pc| (pc, names.next().unwrap()))
+            .collect();
+        let mut out = String::new();
+        for pc in 0..=len {
+            if let Some(label) = labels.get(&pc) {
+                out += &format!("{}:\n", label);
+            }
+            if let Some(instruction) = self.instructions.get(pc) {
+                out += "    ";
+                out += instruction.op.name;
+                for (param, arg) in instruction.op.params.iter().zip(instruction.args()) {
+                    out += " ";
+                    match target(pc, param, arg) {
+                        Some(target) => out += &labels[&target],
+                        None => out += &self.set.format_operand(*arg),
+                    }
+                }
+                out += "\n";
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", self.format(instruction))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_vm() {
+    let program = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+    let mut handheld = InstructionSet::handheld().parse(program).unwrap();
+    assert_eq!(handheld.run_until_repeat(), ProgramHalt::Loop);
+    assert_eq!(handheld.accumulator(), 5);
+
+    // Without loop detection, the instruction limit stops us
+    let mut limited = InstructionSet::handheld()
+        .parse(program)
+        .unwrap()
+        .with_limit(100);
+    assert_eq!(limited.run(), ProgramHalt::LimitReached);
+    assert_eq!(limited.executed, 100);
+
+    let mut escape = InstructionSet::handheld().parse("nop +0\njmp -2").unwrap();
+    assert_eq!(escape.run(), ProgramHalt::OutOfBounds(-1));
+    assert_eq!(escape.program_counter, 1);
+
+    // 5! using a countdown loop
+    let factorial = "cpy 5 a
+cpy 1 b
+mul b a
+add a -1
+jgz a -2
+cpy b acc";
+    let mut program = InstructionSet::extended(&["acc", "a", "b"])
+        .parse(factorial)
+        .unwrap();
+    assert_eq!(program.run(), ProgramHalt::NormalExit);
+    assert_eq!(program.accumulator(), 120);
+    assert_eq!(
+        program.to_string(),
+        factorial.replace(" 5", " +5").replace(" 1", " +1") + "\n"
+    );
+
+    let set = InstructionSet::extended(&["acc", "a", "L0"]);
+    assert!(set.parse_instruction("mul 3 a").is_err());
+    assert!(set.parse_instruction("cpy a z").is_err());
+    assert!(set.parse_instruction("jnz a").is_err());
+    assert!(set.parse_instruction("div a 2").is_err());
+    let mut overflow = set.parse("cpy 9223372036854775807 a\nadd a 1").unwrap();
+    assert_eq!(overflow.run(), ProgramHalt::Overflow);
+    assert_eq!(overflow.program_counter, 1);
+}
+
+#[test]
+fn test_trace() {
+    let program = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+    let mut looping = InstructionSet::handheld()
+        .parse(program)
+        .unwrap()
+        .with_trace();
+    assert_eq!(looping.run_until_repeat(), ProgramHalt::Loop);
+    let trace = looping.trace.clone().unwrap();
+    let pcs: Vec<usize> = trace.steps.iter().map(|step| step.pc).collect();
+    assert_eq!(pcs, vec![0, 1, 2, 6, 7, 3, 4]);
+    let deltas: Vec<i64> = trace.steps.iter().map(|step| step.delta).collect();
+    assert_eq!(deltas, vec![0, 1, 0, 1, 0, 3, 0]);
+    let cycle = trace.cycle().unwrap();
+    assert_eq!(cycle.entry, 1);
+    assert_eq!(cycle.body, &trace.steps[1..]);
+    assert_eq!(trace.hit_counts(9), vec![1, 1, 1, 1, 1, 0, 1, 1, 0]);
+
+    let text = trace.to_text(&looping);
+    assert!(
+        text.starts_with("7 steps, ending at 1\nLoop of 6 steps entered at 1, changing acc by 5")
+    );
+    assert!(text.contains("    5      0   acc -99\n"));
+    assert!(text.contains("    7      1 * jmp -4\n"));
+    let json = trace.to_json(&looping);
+    assert!(json.starts_with(
+        "{\"end\":1,\"cycle\":{\"entry\":1,\"body\":[1,2,6,7,3,4]},\"hits\":[1,1,1,1,1,0,1,1,0]"
+    ));
+
+    let mut exits = InstructionSet::handheld()
+        .parse("acc +2\njmp +2\nacc +5\nacc -1")
+        .unwrap()
+        .with_trace();
+    assert_eq!(exits.run(), ProgramHalt::NormalExit);
+    let trace = exits.trace.as_ref().unwrap();
+    assert_eq!(trace.end, 4);
+    assert_eq!(trace.cycle(), None);
+    assert!(trace.to_text(&exits).contains("No loop\n"));
+    assert!(trace
+        .to_json(&exits)
+        .starts_with("{\"end\":4,\"cycle\":null"));
+
+    // Stopping on an instruction that already ran isn't a loop by itself
+    let mut escapes = InstructionSet::extended(&["a"])
+        .parse("cpy 0 a\njnz a +5\nadd a 1\njmp -2")
+        .unwrap()
+        .with_trace();
+    assert_eq!(escapes.run(), ProgramHalt::OutOfBounds(6));
+    let trace = escapes.trace.as_ref().unwrap();
+    assert_eq!(trace.end, 1);
+    assert_eq!(trace.halt, Some(ProgramHalt::OutOfBounds(6)));
+    assert_eq!(trace.cycle(), None);
+    let mut limited = InstructionSet::handheld()
+        .parse("jmp +0")
+        .unwrap()
+        .with_limit(5)
+        .with_trace();
+    assert_eq!(limited.run(), ProgramHalt::LimitReached);
+    assert_eq!(limited.trace.as_ref().unwrap().cycle(), None);
+}
+
+#[test]
+fn test_round_trip() {
+    let assembly = "; count down from 3
+    cpy 3 a
+loop:
+    add a -1
+    jnz a loop ; back to the top
+    jmp end
+    hlt
+end:
+";
+    let set = InstructionSet::extended(&["acc", "a", "L0"]);
+    let program = set.clone().parse(assembly).unwrap();
+    assert_eq!(
+        program.to_string(),
+        "cpy +3 a\nadd a -1\njnz a -1\njmp +2\nhlt\n"
+    );
+    assert_eq!(
+        program.disassemble(),
+        "    cpy +3 a\nL1:\n    add a -1\n    jnz a L1\n    jmp L2\n    hlt\nL2:\n"
+    );
+    let clash = set.clone().parse("add L0 -1\njnz L0 -1").unwrap();
+    assert_eq!(clash.disassemble(), "L1:\n    add L0 -1\n    jnz L0 L1\n");
+    assert!(set.clone().parse("x:\nx:\nhlt").is_err());
+    assert!(set.clone().parse("a:\nhlt").is_err());
+    assert!(set.clone().parse("x:\ncpy x a").is_err());
+    assert!(set.clone().decode(b"VM\x01\x01\x63").is_err());
+    // A value operand one bit wider than 64 bits
+    let wide = [&b"VM\x01\x01\x03\x03"[..], &[0x80; 9], &[0x04]].concat();
+    assert_eq!(
+        set.clone().decode(&wide).err(),
+        Some("Value too large in instruction 0".to_string())
+    );
+    assert!(set
+        .clone()
+        .decode(&[program.encode(), vec![0]].concat())
+        .is_err());
+
+    // Random programs should survive every serialisation round trip
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut random = move |n: u64| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state % n
+    };
+    let ops = [ACC, JMP, NOP, ADD, MUL, CPY, JNZ, JGZ, HLT];
+    for _ in 0..500 {
+        let len = random(20) as usize;
+        let instructions = (0..len)
+            .map(|_| {
+                let op = ops[random(ops.len() as u64) as usize];
+                let args: Vec<Operand> = op
+                    .params
+                    .iter()
+                    .map(|param| match (param, random(4)) {
+                        (Param::Register, _) | (_, 0) => Operand::Register(random(3) as usize),
+                        (_, 1) => Operand::Value([i64::MIN, i64::MAX][random(2) as usize]),
+                        _ => Operand::Value(random(2 * len as u64 + 5) as i64 - len as i64 - 2),
+                    })
+                    .collect();
+                Instruction::new(op, &args)
+            })
+            .collect();
+        let program = Program::new(Rc::new(set.clone()), instructions);
+        let from_text = set.clone().parse(&program.to_string()).unwrap();
+        assert_eq!(from_text.instructions, program.instructions);
+        let from_labels = set.clone().parse(&program.disassemble()).unwrap();
+        assert_eq!(from_labels.instructions, program.instructions);
+        let from_binary = set.clone().decode(&program.encode()).unwrap();
+        assert_eq!(from_binary.instructions, program.instructions);
+    }
+}